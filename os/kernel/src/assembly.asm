@@ -323,10 +323,20 @@ _user_trap:
     ld      t6, 536(a0)
     csrw    stvec, t6
     # install kernel page table
+    # flush only when the user space has no ASID of its own(ASID 0 is the kernel's)
     ld      t6, 528(a0)
+    csrr    t0, satp
+    srli    t0, t0, 44
+    li      t1, 0xffff
+    and     t0, t0, t1
+    bnez    t0, 4f
     sfence.vma
     csrw    satp, t6
     sfence.vma
+    j       5f
+4:
+    csrw    satp, t6
+5:
     # prepare arguments
     csrr    a0, scause
     csrr    a1, stval
@@ -334,10 +344,18 @@ _user_trap:
     jalr    t0
     # a0 -> satp
     # a1 -> &trapframe(inaccessible before satp install)
-    # install context
+    # install context, the same as above
+    srli    t0, a0, 44
+    li      t1, 0xffff
+    and     t0, t0, t1
+    bnez    t0, 6f
     sfence.vma
     csrw    satp, a0
     sfence.vma
+    j       7f
+6:
+    csrw    satp, a0
+7:
 # _restore(satp: usize, trampframe: &TrampFrame)
 _restore:
     # traps here redirect to user trap
@@ -475,5 +493,11 @@ _switch_internal:
     add     ra, a0, t0
     mv      a0, a1
     mv      a1, a2
+    # the same as _user_trap, no flush for a space with its own ASID
+    srli    t0, a0, 44
+    li      t1, 0xffff
+    and     t0, t0, t1
+    bnez    t0, 8f
     sfence.vma  zero,zero
+8:
     ret
\ No newline at end of file
@@ -7,6 +7,8 @@ use dtb_parser::{
 };
 use erhino_shared::mem::Address;
 
+use crate::hart;
+
 use self::device::{cpu::MmuType, DeviceMap};
 
 pub mod device;
@@ -17,6 +19,7 @@ pub struct BoardInfo {
     tree: DeviceTree,
     map: DeviceMap,
     initfs: Option<(Address, usize)>,
+    timebase_frequency: usize,
 }
 
 impl BoardInfo {
@@ -81,6 +84,7 @@ impl BoardInfo {
             initfs,
             tree,
             map: built,
+            timebase_frequency,
         })
     }
 
@@ -91,6 +95,24 @@ impl BoardInfo {
     pub fn map(&self) -> &DeviceMap {
         &self.map
     }
+
+    // mtime 的频率，0 表示设备树没有给出
+    pub fn timebase_frequency(&self) -> usize {
+        self.timebase_frequency
+    }
+
+    // 设备树没给 timebase-frequency 的时候退回到第一个应用核心的频率，都没有为 0
+    pub fn mtime_frequency(&self) -> usize {
+        if self.timebase_frequency != 0 {
+            self.timebase_frequency
+        } else {
+            self.map
+                .cpus()
+                .iter()
+                .find(|cpu| hart::is_application(cpu))
+                .map_or(0, |cpu| cpu.freq())
+        }
+    }
 }
 
 pub fn init(tree: DeviceTree) {
@@ -1,6 +1,7 @@
 use crate::hart::HartId;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+// 按地址空间从窄到宽排列，支持宽的模式也就支持比它窄的
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MmuType {
     Bare,
     Sv32,
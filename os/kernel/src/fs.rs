@@ -124,10 +124,11 @@ pub fn measure(dentry: &Dentry) -> usize {
                 ) {
                     size + measure(&mounted)
                 } else {
-                    todo!("no root");
+                    size
                 }
             } else {
-                todo!("foreign")
+                // 外部文件系统的内容要问服务进程，这里只算挂载点本身
+                size
             }
         }
         _ => size,
@@ -174,11 +175,7 @@ pub fn make_objects<'a>(dentry: &Dentry, buffer: &'a mut Vec<(DentryObject, Stri
                     Path::from("/").unwrap(),
                 ) {
                     make_objects(&mounted, buffer)
-                } else {
-                    todo!("no root");
                 }
-            } else {
-                todo!("foreign")
             }
         }
         _ => {}
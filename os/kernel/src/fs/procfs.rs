@@ -25,12 +25,16 @@ enum FsLayer {
     MemoryProgram(Pid),
     MemoryHeap(Pid),
     MemoryStack(Pid),
+    MemoryMapped(Pid),
+    MemoryPageLimit(Pid),
+    MemoryHeapLimit(Pid),
+    MemoryStackLimit(Pid),
 }
 
 // 结构
 // 挂载到 rootfs 的 /proc
 // (/proc)/{pid}/{prop}
-// (/proc)/{pid}/memory/{prop}
+// (/proc)/{pid}/memory/{prop}，用量 page 按页，program、heap、stack、mapped 按字节，*_limit 是对应的上限
 // (/proc)/{pid}/traits/{trait}
 
 pub struct Procfs {}
@@ -56,6 +60,10 @@ impl Procfs {
                                                 "program" => Ok(FsLayer::MemoryProgram(id)),
                                                 "heap" => Ok(FsLayer::MemoryHeap(id)),
                                                 "stack" => Ok(FsLayer::MemoryStack(id)),
+                                                "mapped" => Ok(FsLayer::MemoryMapped(id)),
+                                                "page_limit" => Ok(FsLayer::MemoryPageLimit(id)),
+                                                "heap_limit" => Ok(FsLayer::MemoryHeapLimit(id)),
+                                                "stack_limit" => Ok(FsLayer::MemoryStackLimit(id)),
                                                 _ => Err(FilesystemAbstractLayerError::NotFound),
                                             }
                                         } else {
@@ -100,6 +108,19 @@ impl Procfs {
             }
             FsLayer::MemoryHeap(_) => buffer = Some((p.usage.heap as i64).to_ne_bytes().to_vec()),
             FsLayer::MemoryStack(_) => buffer = Some((p.usage.stack as i64).to_ne_bytes().to_vec()),
+            FsLayer::MemoryMapped(_) => {
+                buffer = Some((p.usage.mapped as i64).to_ne_bytes().to_vec())
+            }
+            // 没有上限时是 usize::MAX，按 i64 读出来是 -1
+            FsLayer::MemoryPageLimit(_) => {
+                buffer = Some((p.limit.page as i64).to_ne_bytes().to_vec())
+            }
+            FsLayer::MemoryHeapLimit(_) => {
+                buffer = Some((p.limit.heap as i64).to_ne_bytes().to_vec())
+            }
+            FsLayer::MemoryStackLimit(_) => {
+                buffer = Some((p.limit.stack as i64).to_ne_bytes().to_vec())
+            }
             _ => {}
         });
         if let Some(res) = buffer {
@@ -213,6 +234,38 @@ impl FileSystem for Procfs {
                             DentryAttribute::Readable.into(),
                             DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
                         ),
+                        Dentry::new(
+                            "mapped".to_owned(),
+                            0,
+                            0,
+                            size_of::<i64>(),
+                            DentryAttribute::Readable.into(),
+                            DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                        ),
+                        Dentry::new(
+                            "page_limit".to_owned(),
+                            0,
+                            0,
+                            size_of::<i64>(),
+                            DentryAttribute::Readable.into(),
+                            DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                        ),
+                        Dentry::new(
+                            "heap_limit".to_owned(),
+                            0,
+                            0,
+                            size_of::<i64>(),
+                            DentryAttribute::Readable.into(),
+                            DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                        ),
+                        Dentry::new(
+                            "stack_limit".to_owned(),
+                            0,
+                            0,
+                            size_of::<i64>(),
+                            DentryAttribute::Readable.into(),
+                            DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                        ),
                     ];
                     Ok(Dentry::new(
                         "memory".to_owned(),
@@ -255,6 +308,38 @@ impl FileSystem for Procfs {
                     DentryAttribute::Readable.into(),
                     DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
                 )),
+                FsLayer::MemoryMapped(_) => Ok(Dentry::new(
+                    "mapped".to_owned(),
+                    0,
+                    0,
+                    size_of::<i64>(),
+                    DentryAttribute::Readable.into(),
+                    DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                )),
+                FsLayer::MemoryPageLimit(_) => Ok(Dentry::new(
+                    "page_limit".to_owned(),
+                    0,
+                    0,
+                    size_of::<i64>(),
+                    DentryAttribute::Readable.into(),
+                    DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                )),
+                FsLayer::MemoryHeapLimit(_) => Ok(Dentry::new(
+                    "heap_limit".to_owned(),
+                    0,
+                    0,
+                    size_of::<i64>(),
+                    DentryAttribute::Readable.into(),
+                    DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                )),
+                FsLayer::MemoryStackLimit(_) => Ok(Dentry::new(
+                    "stack_limit".to_owned(),
+                    0,
+                    0,
+                    size_of::<i64>(),
+                    DentryAttribute::Readable.into(),
+                    DentryMeta::File(FileKind::Property(PropertyKind::Integer)),
+                )),
             }
         } else {
             Err(FilesystemAbstractLayerError::InvalidPath)
@@ -281,6 +366,10 @@ impl FileSystem for Procfs {
                 FsLayer::MemoryProgram(pid) => Self::read_prop(pid, layer),
                 FsLayer::MemoryHeap(pid) => Self::read_prop(pid, layer),
                 FsLayer::MemoryStack(pid) => Self::read_prop(pid, layer),
+                FsLayer::MemoryMapped(pid) => Self::read_prop(pid, layer),
+                FsLayer::MemoryPageLimit(pid) => Self::read_prop(pid, layer),
+                FsLayer::MemoryHeapLimit(pid) => Self::read_prop(pid, layer),
+                FsLayer::MemoryStackLimit(pid) => Self::read_prop(pid, layer),
             }
         } else {
             Err(FilesystemAbstractLayerError::InvalidPath)
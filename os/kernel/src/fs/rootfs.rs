@@ -15,7 +15,7 @@ use erhino_shared::{
 use flagset::FlagSet;
 use lock_api::RawMutex;
 
-use crate::sync::up::UpSafeCell;
+use crate::{sync::up::UpSafeCell, timer};
 
 type Node = UpSafeCell<LocalDentry>;
 
@@ -190,7 +190,8 @@ impl LocalDentry {
     }
 
     pub fn replace(&mut self, kind: LocalDentryKind) {
-        self.kind = kind
+        self.kind = kind;
+        self.modified = timer::monotonic();
     }
 
     pub fn name(&self) -> &str {
@@ -380,8 +381,8 @@ impl Rootfs {
         Self {
             root: UpSafeCell::new(LocalDentry::new_directory(
                 "",
-                0,
-                0,
+                timer::monotonic(),
+                timer::monotonic(),
                 DentryAttribute::Readable | DentryAttribute::Executable,
             )),
         }
@@ -393,9 +394,10 @@ impl Rootfs {
         // 然后由 /proc/{pid}/traits/fs/* 得到其支持的文件系统信息
         // Self::mount 不会检查 service traits，但用户接口中的 mount 会
         if let Some(parent) = path.parent() {
+            let now = timer::monotonic();
             self.create_node(
                 &parent,
-                LocalDentry::new_mountpoint(path.filename(), 0, 0, mountpoint),
+                LocalDentry::new_mountpoint(path.filename(), now, now, mountpoint),
             )
         } else {
             Err(FilesystemAbstractLayerError::InvalidPath)
@@ -410,9 +412,10 @@ impl Rootfs {
         attr: FlagSet<DentryAttribute>,
     ) -> Result<(), FilesystemAbstractLayerError> {
         if let Some(parent) = path.parent() {
+            let now = timer::monotonic();
             self.create_node(
                 &parent,
-                LocalDentry::new_memory_stream(path.filename(), 0, 0, attr, address, length),
+                LocalDentry::new_memory_stream(path.filename(), now, now, attr, address, length),
             )
         } else {
             Err(FilesystemAbstractLayerError::InvalidPath)
@@ -523,37 +526,38 @@ impl FileSystem for Rootfs {
     ) -> Result<(), FilesystemAbstractLayerError> {
         if let Some(parent) = path.parent() {
             // 只支持内存属性，要添加内存流要用 Rootfs.create_stream
+            let now = timer::monotonic();
             match kind {
                 DentryType::Directory => self.create_node(
                     &parent,
-                    LocalDentry::new_directory(path.filename(), 0, 0, attr),
+                    LocalDentry::new_directory(path.filename(), now, now, attr),
                 ),
                 DentryType::Boolean => self.create_node(
                     &parent,
-                    LocalDentry::new_boolean(path.filename(), 0, 0, attr),
+                    LocalDentry::new_boolean(path.filename(), now, now, attr),
                 ),
                 DentryType::Integer => self.create_node(
                     &parent,
-                    LocalDentry::new_integer(path.filename(), 0, 0, attr),
+                    LocalDentry::new_integer(path.filename(), now, now, attr),
                 ),
                 DentryType::Integers => self.create_node(
                     &parent,
-                    LocalDentry::new_integers(path.filename(), 0, 0, attr),
+                    LocalDentry::new_integers(path.filename(), now, now, attr),
                 ),
                 DentryType::Decimal => self.create_node(
                     &parent,
-                    LocalDentry::new_decimal(path.filename(), 0, 0, attr),
+                    LocalDentry::new_decimal(path.filename(), now, now, attr),
                 ),
                 DentryType::Decimals => self.create_node(
                     &parent,
-                    LocalDentry::new_decimals(path.filename(), 0, 0, attr),
+                    LocalDentry::new_decimals(path.filename(), now, now, attr),
                 ),
                 DentryType::String => self.create_node(
                     &parent,
-                    LocalDentry::new_string(path.filename(), 0, 0, attr),
+                    LocalDentry::new_string(path.filename(), now, now, attr),
                 ),
                 DentryType::Blob => {
-                    self.create_node(&parent, LocalDentry::new_blob(path.filename(), 0, 0, attr))
+                    self.create_node(&parent, LocalDentry::new_blob(path.filename(), now, now, attr))
                 }
                 _ => Err(FilesystemAbstractLayerError::Unsupported),
             }
@@ -3,11 +3,14 @@ use core::arch::asm;
 use alloc::vec::Vec;
 
 use crate::{
-    board::{self, device::cpu::MmuType},
-    rng::lcg::LcGenerator,
+    board::{
+        self,
+        device::cpu::{Cpu, MmuType},
+    },
+    rng::chacha::ChaChaGenerator,
     sbi,
     task::sched::unfair::UnfairScheduler,
-    timer::{cpu::CpuClock, Timer},
+    timer::{self, cpu::CpuClock},
 };
 
 use self::app::ApplicationHart;
@@ -18,7 +21,7 @@ pub type HartId = usize;
 
 pub type TimerImpl = CpuClock;
 pub type SchedulerImpl = UnfairScheduler<TimerImpl>;
-pub type RandomImpl = LcGenerator;
+pub type RandomImpl = ChaChaGenerator;
 
 static mut HARTS: Vec<HartKind> = Vec::new();
 
@@ -34,14 +37,19 @@ pub enum HartKind {
     Application(ApplicationHart<SchedulerImpl, RandomImpl>),
 }
 
-pub fn init() {
+// 内核只在能用 RV64 分页模式的核心上调度，sifive_u 的 E51 这类监控核心不算
+pub fn is_application(cpu: &Cpu) -> bool {
+    cpu.mmu() >= MmuType::Sv39
+}
+
+pub fn init(frequency: usize) {
     let board = board::this_board();
     let harts = unsafe { &mut HARTS };
     for cpu in board
         .map()
         .cpus()
         .iter()
-        .filter(|maybe| maybe.mmu() != MmuType::Bare)
+        .filter(|maybe| is_application(maybe))
     {
         if cpu.id() > harts.len() {
             let diff = cpu.id() - harts.len();
@@ -49,12 +57,13 @@ pub fn init() {
                 harts.push(HartKind::Disabled);
             }
         }
-        let timer = TimerImpl::new(cpu.freq());
-        let seed = timer.uptime();
+        // 调度器还没跑起来，uptime 恒为 0，只能拿 mtime 当种子。种子能猜，生成的数不能当秘密用
+        let seed = timer::cpu::time();
+        let timer = TimerImpl::new(frequency);
         let hart = ApplicationHart::new(
             cpu.id(),
             UnfairScheduler::new(cpu.id(), timer),
-            RandomImpl::new(seed),
+            RandomImpl::new(seed, cpu.id()),
         );
         harts.push(HartKind::Application(hart));
     }
@@ -8,12 +8,20 @@ use core::{
 use alloc::{string::String, vec::Vec};
 use erhino_shared::{
     call::{SystemCall, SystemCallError},
-    fal::{DentryAttribute, DentryObject, DentryType, FilesystemAbstractLayerError},
-    mem::{Address, MemoryRegionAttribute},
+    fal::{
+        DentryAttribute, DentryMeta, DentryObject, DentryType, FileKind,
+        FilesystemAbstractLayerError,
+    },
+    mem::{Address, MemoryLimit, MemoryOperation, MemoryRegionAttribute, PageNumber},
     message::MessageDigest,
     path::Path,
-    proc::{ExitCode, Pid, SignalMap},
+    proc::{
+        ExecutionState, ExitCode, Pid, ProcessPermission, Rid, SignalMap, SignalMaskOperation,
+        StartupArguments, SystemSignal, Tid, EXIT_CODE_ILLEGAL_INSTRUCTION, EXIT_CODE_KILLED,
+        EXIT_CODE_MISALIGNED, EXIT_CODE_SEGMENTATION_FAULT, PERMISSIONS_INHERITED, STARTUP_LIMIT,
+    },
     sync::spin::SimpleLock,
+    time::{Timestamp, NS_PER_SEC},
 };
 use flagset::FlagSet;
 use lock_api::Mutex;
@@ -21,7 +29,7 @@ use num_traits::FromPrimitive;
 
 use crate::{
     debug,
-    external::{_awaken, _park, _switch},
+    external::{_awaken, _memory_end, _memory_start, _park, _switch},
     fs::{self},
     mm::{
         frame,
@@ -32,11 +40,16 @@ use crate::{
     rng::RandomGenerator,
     sbi,
     task::{
-        ipc::{message::Message, tunnel::Tunnel},
-        proc::{ProcessHealth, ProcessTunnelError},
-        sched::{ScheduleContext, Scheduler},
+        ipc::{
+            message::{Message, MAILBOX_CAPACITY_LIMIT},
+            tunnel::{Tunnel, TUNNEL_PAGE_LIMIT, TUNNEL_PAGE_LIMIT_PER_PROCESS},
+        },
+        proc::{Process, ProcessHealth, ProcessTunnelError},
+        request::{self, KernelRequest, KernelRequestKind},
+        sched::{ScheduleContext, Scheduler, ThreadJoinState},
         thread::Thread,
     },
+    timer,
     trap::TrapCause,
 };
 
@@ -44,6 +57,8 @@ use super::{enter_user, send_ipi, HartStatus, HartId};
 
 static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
 static TUNNELS: Mutex<SimpleLock, Vec<Tunnel>> = Mutex::new(Vec::new());
+// 闹钟最远能定到多久以后
+const ALARM_LIMIT: Timestamp = 24 * 60 * 60 * NS_PER_SEC;
 
 pub struct ApplicationHart<S, R> {
     id: usize,
@@ -137,9 +152,17 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
         arg1: usize,
         arg2: usize,
         arg3: usize,
+        arg4: usize,
+        arg5: usize,
+        fed: Option<KernelRequest>,
         random: &mut R,
     ) -> Result<Option<usize>, SystemCallError> {
         let process = context.process();
+        if let Some(required) = required_permission(call)
+            && !process.has_permission(required)
+        {
+            return Err(SystemCallError::PermissionDenied);
+        }
         match call {
             SystemCall::Die => {
                 panic!("Die die die!");
@@ -163,9 +186,166 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
             }
             SystemCall::Exit => {
                 let code = arg0 as ExitCode;
-                process.health = ProcessHealth::Dead(code);
+                Self::exit(context, code);
                 Ok(None)
             }
+            SystemCall::Wait => {
+                let target = arg0 as Pid;
+                let code_address = arg1 as Address;
+                // 先登记再检查，避免检查完到挂起之间子进程退出导致错过唤醒
+                let rid = request::issue(
+                    context.pid(),
+                    context.tid(),
+                    KernelRequestKind::ChildExit(target),
+                );
+                let mut living = false;
+                let mut exited: Option<(Pid, ExitCode)> = None;
+                for (child, health) in context.children() {
+                    if target == 0 || child == target {
+                        if let ProcessHealth::Dead(code) = health {
+                            exited = Some((child, code));
+                            break;
+                        } else {
+                            living = true;
+                        }
+                    }
+                }
+                if let Some((child, code)) = exited {
+                    request::cancel(rid);
+                    if code_address != 0 {
+                        if let Err(err) =
+                            process.write(code_address, &code.to_ne_bytes(), size_of::<ExitCode>())
+                        {
+                            return Err(err.into());
+                        }
+                    }
+                    Self::reap(context, child);
+                    Ok(Some(child as usize))
+                } else if living {
+                    context.thread().state = ExecutionState::Pending(rid);
+                    Ok(None)
+                } else {
+                    request::cancel(rid);
+                    Err(SystemCallError::ObjectNotFound)
+                }
+            }
+            SystemCall::Restrict => {
+                if let Ok(kept) = FlagSet::<ProcessPermission>::new(arg0 as u32) {
+                    let remaining = process.restrict(kept);
+                    debug!(
+                        "app.restrict Pid={} permissions now {:?}",
+                        context.pid(),
+                        remaining
+                    );
+                    Ok(Some(remaining.bits() as usize))
+                } else {
+                    Err(SystemCallError::IllegalArgument)
+                }
+            }
+            SystemCall::Fork => {
+                // 子进程的 TrapFrame 在这里复制，返回之后父进程才前进到下一条指令
+                if let Some(pid) = context.fork() {
+                    debug!("app.fork Pid={} forked Pid={}", context.pid(), pid);
+                    Ok(Some(pid as usize))
+                } else {
+                    Err(SystemCallError::OutOfMemory)
+                }
+            }
+            SystemCall::ExecuteBytes => {
+                let address = arg0;
+                let length = arg1;
+                let permissions = inherit_permissions(process, arg2)?;
+                let (arguments, variables, limit) = read_startup(process, arg3)?;
+                match process.read(address, length) {
+                    Ok(bytes) => match Process::from_elf(&bytes) {
+                        Ok(mut child) => {
+                            child.set_permissions(permissions);
+                            child.set_startup(arguments, variables);
+                            child.limit = process.limit.inherit(&limit);
+                            if let Some(pid) = context.add_proc(child) {
+                                debug!(
+                                    "app.execute Pid={} spawned Pid={} from {:#x} bytes",
+                                    context.pid(),
+                                    pid,
+                                    length
+                                );
+                                Ok(Some(pid as usize))
+                            } else {
+                                Err(SystemCallError::OutOfMemory)
+                            }
+                        }
+                        Err(err) => Err(err.into()),
+                    },
+                    Err(err) => Err(err.into()),
+                }
+            }
+            SystemCall::ExecuteFile => {
+                let path_address = arg0;
+                let path_length = arg1;
+                let permissions = inherit_permissions(process, arg2)?;
+                let (arguments, variables, limit) = read_startup(process, arg3)?;
+                match process.read(path_address, path_length) {
+                    Ok(path_buffer) => {
+                        if let Ok(str) = String::from_utf8(path_buffer) {
+                            if let Ok(path) = Path::from(&str) {
+                                match fs::lookup(path.clone()) {
+                                    Ok(dentry) => {
+                                        if let DentryMeta::File(FileKind::Stream) = dentry.meta() {
+                                            match fs::read(path, dentry.size()) {
+                                                Ok(bytes) => match Process::from_elf(&bytes) {
+                                                    Ok(mut child) => {
+                                                        child.set_permissions(permissions);
+                                                        child.set_startup(arguments, variables);
+                                                        child.limit = process.limit.inherit(&limit);
+                                                        if let Some(pid) = context.add_proc(child)
+                                                        {
+                                                            debug!(
+                                                                "app.execute Pid={} spawned Pid={} from {}",
+                                                                context.pid(),
+                                                                pid,
+                                                                str
+                                                            );
+                                                            Ok(Some(pid as usize))
+                                                        } else {
+                                                            Err(SystemCallError::OutOfMemory)
+                                                        }
+                                                    }
+                                                    Err(err) => Err(err.into()),
+                                                },
+                                                Err(_) => Err(SystemCallError::ObjectNotAccessible),
+                                            }
+                                        } else {
+                                            Err(SystemCallError::NotSupported)
+                                        }
+                                    }
+                                    Err(err) => match err {
+                                        FilesystemAbstractLayerError::NotAccessible => {
+                                            Err(SystemCallError::ObjectNotAccessible)
+                                        }
+                                        FilesystemAbstractLayerError::InvalidPath => {
+                                            Err(SystemCallError::IllegalArgument)
+                                        }
+                                        FilesystemAbstractLayerError::NotFound => {
+                                            Err(SystemCallError::ObjectNotFound)
+                                        }
+                                        // 外部文件系统的流需要隧道读取，暂不支持从其执行
+                                        FilesystemAbstractLayerError::ForeignMountPoint(
+                                            _rem,
+                                            _mid,
+                                        ) => Err(SystemCallError::NotSupported),
+                                        _ => Err(SystemCallError::InternalError),
+                                    },
+                                }
+                            } else {
+                                Err(SystemCallError::IllegalArgument)
+                            }
+                        } else {
+                            Err(SystemCallError::IllegalArgument)
+                        }
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
             SystemCall::Extend => {
                 let bytes = arg0;
                 debug!(
@@ -178,20 +358,213 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                     .map_err(|err| err.into())
                     .map(|r| Some(r))
             }
+            SystemCall::Map => {
+                let address = arg0 as Address;
+                let count = page_count(arg1)?;
+                let attributes = map_attributes(arg2)?;
+                match process.map_anonymous(address, count, attributes) {
+                    Ok(start) => {
+                        debug!(
+                            "app.map Pid={} {:#x} pages at {:#x}",
+                            context.pid(),
+                            count,
+                            start
+                        );
+                        Ok(Some(start))
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
+            SystemCall::MapPhysical => {
+                let address = arg0 as Address;
+                let physical = arg1 as Address;
+                let count = page_count(arg2)?;
+                let attributes = map_attributes(arg3)?;
+                if physical & (PAGE_SIZE - 1) != 0 {
+                    return Err(SystemCallError::InvalidAddress);
+                }
+                // 只给设备空间，内存里是内核和别的进程的页
+                let first = physical >> PAGE_BITS;
+                let memory_start = _memory_start as usize >> PAGE_BITS;
+                let memory_end = _memory_end as usize >> PAGE_BITS;
+                match first.checked_add(count) {
+                    Some(last) if last <= memory_start || first >= memory_end => {}
+                    _ => return Err(SystemCallError::MemoryNotAccessible),
+                }
+                match process.map_physical(address, first, count, attributes) {
+                    Ok(start) => {
+                        debug!(
+                            "app.map Pid={} {:#x} physical pages {:#x} at {:#x}",
+                            context.pid(),
+                            count,
+                            physical,
+                            start
+                        );
+                        Ok(Some(start))
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
+            SystemCall::Free => {
+                let address = arg0 as Address;
+                let count = page_count(arg1)?;
+                process
+                    .unmap(address, count)
+                    .map_err(|err| err.into())
+                    .map(|freed| Some(freed))
+            }
             SystemCall::ThreadSpawn => {
                 let func_pointer = arg0 as Address;
-                let thread = Thread::new(func_pointer);
-                let tid = context.add_thread(thread);
-                Ok(Some(tid as usize))
+                let argument = arg1;
+                let thread = Thread::new(func_pointer, argument);
+                if let Some(tid) = context.add_thread(thread) {
+                    Ok(Some(tid as usize))
+                } else {
+                    Err(SystemCallError::ReachLimit)
+                }
+            }
+            SystemCall::ThreadExit => {
+                let code = arg0 as ExitCode;
+                if context.tid() == 0 {
+                    // 主线程退出等同于进程退出
+                    Self::exit(context, code);
+                } else {
+                    context.kill_thread(context.tid(), code);
+                }
+                Ok(None)
+            }
+            SystemCall::ThreadYield => {
+                context.schedule();
+                Ok(Some(0))
+            }
+            SystemCall::ThreadJoin => {
+                let tid = arg0 as Tid;
+                let code_address = arg1 as Address;
+                if tid == context.tid() {
+                    return Err(SystemCallError::IllegalArgument);
+                }
+                // 先登记再检查，同 Wait
+                let rid = request::issue(
+                    context.pid(),
+                    context.tid(),
+                    KernelRequestKind::ThreadExit(tid),
+                );
+                match context.join_thread(tid) {
+                    ThreadJoinState::Exited(code) => {
+                        request::cancel(rid);
+                        if code_address != 0 {
+                            if let Err(err) = process.write(
+                                code_address,
+                                &code.to_ne_bytes(),
+                                size_of::<ExitCode>(),
+                            ) {
+                                return Err(err.into());
+                            }
+                        }
+                        Ok(Some(0))
+                    }
+                    ThreadJoinState::Living => {
+                        context.thread().state = ExecutionState::Pending(rid);
+                        Ok(None)
+                    }
+                    ThreadJoinState::NotFound => {
+                        request::cancel(rid);
+                        Err(SystemCallError::ObjectNotFound)
+                    }
+                }
+            }
+            SystemCall::ThreadKill => {
+                let tid = arg0 as Tid;
+                if tid == 0 {
+                    // 杀主线程请直接退出进程
+                    Err(SystemCallError::IllegalArgument)
+                } else if context.kill_thread(tid, -1) {
+                    if tid == context.tid() {
+                        Ok(None)
+                    } else {
+                        Ok(Some(0))
+                    }
+                } else {
+                    Err(SystemCallError::ObjectNotFound)
+                }
+            }
+            SystemCall::FutexWait => {
+                let address = arg0 as Address;
+                let expected = arg1 as u32;
+                let deadline = arg2 as Timestamp;
+                if fed.is_some() {
+                    // 被叫醒或者到点了，过了期限的都算超时
+                    return Ok(Some(
+                        if deadline != 0 && deadline <= timer::monotonic() {
+                            0
+                        } else {
+                            1
+                        },
+                    ));
+                }
+                if address & (size_of::<u32>() - 1) != 0 {
+                    return Err(SystemCallError::InvalidAddress);
+                }
+                if deadline != 0 && deadline <= timer::monotonic() {
+                    return Ok(Some(0));
+                }
+                // 先登记再读值，唤醒方总是先改值再唤醒，不会错过
+                let rid = request::issue(
+                    context.pid(),
+                    context.tid(),
+                    KernelRequestKind::Futex(address),
+                );
+                match process.read(address, size_of::<u32>()) {
+                    Ok(bytes) => {
+                        let value = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                        if value != expected {
+                            request::cancel(rid);
+                            Ok(Some(1))
+                        } else {
+                            if deadline != 0 {
+                                context.sleep(deadline, rid);
+                            }
+                            context.thread().state = ExecutionState::Pending(rid);
+                            Ok(None)
+                        }
+                    }
+                    Err(err) => {
+                        request::cancel(rid);
+                        Err(err.into())
+                    }
+                }
+            }
+            SystemCall::FutexWake => {
+                let address = arg0 as Address;
+                let count = arg1;
+                let pid = context.pid();
+                Ok(Some(request::resolve_at_most(count, |r| {
+                    r.pid() == pid && r.kind() == KernelRequestKind::Futex(address)
+                })))
             }
             SystemCall::TunnelBuild => {
-                if let Some(frame) = frame::borrow(1) {
+                // 旧的调用不带页数，按一页处理
+                let count = if arg0 == 0 { 1 } else { arg0 };
+                if !count.is_power_of_two() || count > TUNNEL_PAGE_LIMIT {
+                    return Err(SystemCallError::IllegalArgument);
+                }
+                let pid = context.pid();
+                let mut tunnels = TUNNELS.lock();
+                // 持有者离开后通道转到对端名下，也算对端的
+                let owned: usize = tunnels
+                    .iter()
+                    .filter(|t| t.owner == pid)
+                    .map(|t| t.page_count())
+                    .sum();
+                if owned + count > TUNNEL_PAGE_LIMIT_PER_PROCESS {
+                    return Err(SystemCallError::ReachLimit);
+                }
+                if let Some(frame) = frame::borrow(count) {
                     let mut rng = random.next();
-                    let mut tunnels = TUNNELS.lock();
-                    while tunnels.iter().any(|t| t.key() == rng) {
+                    while rng == 0 || tunnels.iter().any(|t| t.key() == rng) {
                         rng = random.next();
                     }
-                    let tunnel = Tunnel::new(rng, context.pid(), frame);
+                    let tunnel = Tunnel::new(rng, pid, frame);
                     tunnels.push(tunnel);
                     Ok(Some(rng))
                 } else {
@@ -200,25 +573,28 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
             }
             SystemCall::TunnelLink => {
                 let key = arg0;
+                let size_address = arg1 as Address;
+                let pid = context.pid();
                 let mut tunnels = TUNNELS.lock();
-                let mut found: Option<&mut Tunnel> = None;
-                for tunnel in tunnels.iter_mut() {
-                    if tunnel.key() == key {
-                        found = Some(tunnel);
-                        break;
+                if let Some(tunnel) = tunnels.iter_mut().find(|t| t.key() == key) {
+                    let count = tunnel.page_count();
+                    if size_address != 0 {
+                        let size = count * PAGE_SIZE;
+                        if let Err(err) =
+                            process.write(size_address, &size.to_ne_bytes(), size_of::<usize>())
+                        {
+                            return Err(err.into());
+                        }
                     }
-                }
-                if let Some(tunnel) = found {
-                    match process.tunnel_insert(key) {
+                    match process.tunnel_insert(key, count) {
                         Ok(slot) => {
                             let addr = process.tunnel_point() + PAGE_SIZE * slot;
-                            let occupied = !tunnel.link(context.pid(), addr >> PAGE_BITS);
-                            if !occupied {
+                            if tunnel.link(pid, addr >> PAGE_BITS) {
                                 if process
                                     .map(
                                         addr >> PAGE_BITS,
                                         tunnel.page_number(),
-                                        1,
+                                        count,
                                         MemoryRegionAttribute::Read | MemoryRegionAttribute::Write,
                                         false,
                                     )
@@ -226,10 +602,12 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                 {
                                     Ok(Some(addr))
                                 } else {
-                                    tunnel.unlink(context.pid());
+                                    tunnel.unlink(pid);
+                                    process.tunnel_eject(key);
                                     Err(SystemCallError::MemoryNotAccessible)
                                 }
                             } else {
+                                process.tunnel_eject(key);
                                 Err(SystemCallError::ObjectNotAccessible)
                             }
                         }
@@ -241,28 +619,47 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                     Err(SystemCallError::ObjectNotFound)
                 }
             }
-            SystemCall::TunnelDispose => {
+            SystemCall::TunnelGrant => {
                 let key = arg0;
+                let target = arg1 as Pid;
+                // 先确认目标存在，持有 TUNNELS 时不能再去锁别的进程
+                if !context.find(target, |_| {}) {
+                    return Err(SystemCallError::ObjectNotFound);
+                }
                 let mut tunnels = TUNNELS.lock();
-                let mut found: Option<&mut Tunnel> = None;
-                let mut delete_index = 0usize;
-                for (index, tunnel) in tunnels.iter_mut().enumerate() {
-                    if tunnel.key() == key {
-                        delete_index = index;
-                        found = Some(tunnel);
-                        break;
+                if let Some(tunnel) = tunnels.iter_mut().find(|t| t.key() == key) {
+                    if tunnel.grant(context.pid(), target) {
+                        Ok(Some(0))
+                    } else {
+                        Err(SystemCallError::ObjectNotAccessible)
                     }
+                } else {
+                    Err(SystemCallError::ObjectNotFound)
                 }
-                if let Some(tunnel) = found {
-                    if let Some((delete, number)) = tunnel.unlink(context.pid()) {
+            }
+            SystemCall::TunnelDispose => {
+                let key = arg0;
+                let pid = context.pid();
+                let mut tunnels = TUNNELS.lock();
+                if let Some(index) = tunnels.iter().position(|t| t.key() == key) {
+                    let tunnel = &mut tunnels[index];
+                    let count = tunnel.page_count();
+                    // 先叫醒对端，它回来时会发现隧道已经断了
+                    let signaled = Self::interrupt_peer(tunnel, pid);
+                    if let Some((delete, number)) = tunnel.unlink(pid) {
                         if delete {
-                            tunnels.swap_remove(delete_index);
+                            tunnels.swap_remove(index);
+                        }
+                        drop(tunnels);
+                        if let Some(peer) = signaled {
+                            Self::signal_tunnel_ready(context, peer, key);
                         }
-                        // TODO: 进程退出的时候检查所有 owner 为 pid 的对象，确定 second == None && first == None | pid 后删除（可省略 unlink）
-                        // 进程退出的时候直接按照 tunnels 表删就可以，不需要逐个检查
-                        process.free(number, 1).expect("kill process if failed");
                         process.tunnel_eject(key);
-                        Ok(Some(0))
+                        // 页已经不归它了，解除映射失败也只是进程自己的页表坏了
+                        match process.free(number, count) {
+                            Ok(_) => Ok(Some(0)),
+                            Err(err) => Err(err.into()),
+                        }
                     } else {
                         Err(SystemCallError::ObjectNotAccessible)
                     }
@@ -270,20 +667,108 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                     Err(SystemCallError::ObjectNotFound)
                 }
             }
+            SystemCall::TunnelRequest | SystemCall::TunnelResponse => {
+                // 被对端中断唤醒后回来，铃已经在唤醒时消耗掉了
+                if fed.is_some() {
+                    return Ok(Some(0));
+                }
+                let key = arg0;
+                let wait = arg1 != 0;
+                let pid = context.pid();
+                let mut tunnels = TUNNELS.lock();
+                if let Some(tunnel) = tunnels.iter_mut().find(|t| t.key() == key) {
+                    if !tunnel.is_linked(pid) {
+                        return Err(SystemCallError::ObjectNotAccessible);
+                    }
+                    // 对端已经离开，不会再有中断了
+                    if tunnel.is_broken() {
+                        return Err(SystemCallError::ObjectNotAvailable);
+                    }
+                    let signaled = Self::interrupt_peer(tunnel, pid);
+                    // 在 TUNNELS 锁内登记，对端的中断不会在检查和登记之间溜走
+                    let result = if wait && !tunnel.answer(pid) {
+                        let rid = request::issue(
+                            pid,
+                            context.tid(),
+                            KernelRequestKind::TunnelInterrupt(key),
+                        );
+                        context.thread().state = ExecutionState::Pending(rid);
+                        Ok(None)
+                    } else {
+                        Ok(Some(0))
+                    };
+                    drop(tunnels);
+                    if let Some(peer) = signaled {
+                        Self::signal_tunnel_ready(context, peer, key);
+                    }
+                    result
+                } else {
+                    Err(SystemCallError::ObjectNotFound)
+                }
+            }
             SystemCall::SignalSet => {
-                let mask = arg0;
-                let handler = arg1;
-                process.signal.set_handler(mask as SignalMap, handler);
+                let mask = arg0 as SignalMap;
+                let handler = if arg1 == 0 { None } else { Some(arg1) };
+                if process.signal.set_handler(mask, handler) {
+                    Ok(Some(0))
+                } else {
+                    Err(SystemCallError::IllegalArgument)
+                }
+            }
+            SystemCall::SignalMask => {
+                let mask = arg1 as SignalMap;
+                let previous = process.signal.blocked();
+                match SignalMaskOperation::from_usize(arg0) {
+                    Some(SignalMaskOperation::Block) => process.signal.set_blocked(previous | mask),
+                    Some(SignalMaskOperation::Unblock) => {
+                        process.signal.set_blocked(previous & !mask)
+                    }
+                    Some(SignalMaskOperation::Set) => process.signal.set_blocked(mask),
+                    None => return Err(SystemCallError::IllegalArgument),
+                }
+                Ok(Some(previous as usize))
+            }
+            SystemCall::SignalAlarm => {
+                let deadline = arg0 as Timestamp;
+                if deadline > timer::monotonic().saturating_add(ALARM_LIMIT) {
+                    return Err(SystemCallError::IllegalArgument);
+                }
+                context.alarm(if deadline == 0 { None } else { Some(deadline) });
                 Ok(Some(0))
             }
             SystemCall::SignalSend => {
                 let pid = arg0 as Pid;
                 let signal = arg1 as SignalMap;
+                // 可以随意给自己和子进程发信号，其他的（包括父进程）需要进程管理权限，
+                // 不然被收了权限的子进程能杀掉拉起它的进程
+                if pid != context.pid()
+                    && !process.has_permission(ProcessPermission::Process)
+                    && !context.children().iter().any(|(child, _)| *child == pid)
+                {
+                    return Err(SystemCallError::PermissionDenied);
+                }
+                if !signal.is_power_of_two() {
+                    return Err(SystemCallError::IllegalArgument);
+                }
+                if signal == SystemSignal::Kill as SignalMap {
+                    // 不经过处理函数，直接结束目标
+                    return if Self::terminate(context, pid, EXIT_CODE_KILLED) {
+                        if pid == context.pid() {
+                            Ok(None)
+                        } else {
+                            Ok(Some(1))
+                        }
+                    } else if context.find(pid, |_| {}) {
+                        Ok(Some(0))
+                    } else {
+                        Err(SystemCallError::ObjectNotFound)
+                    };
+                }
                 let mut accepted = false;
                 if context.find(pid, |target| {
-                    if target.signal.is_accepted(signal) {
+                    if target.health == ProcessHealth::Healthy {
+                        accepted = target.signal.is_accepted(signal);
                         target.signal.enqueue(signal);
-                        accepted = true;
                     }
                 }) {
                     Ok(Some(if accepted { 1 } else { 0 }))
@@ -299,6 +784,22 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                     Err(SystemCallError::FunctionNotAvailable)
                 }
             }
+            SystemCall::Clock => Ok(Some(timer::monotonic() as usize)),
+            SystemCall::Sleep => {
+                let deadline = arg0 as Timestamp;
+                if deadline <= timer::monotonic() {
+                    Ok(Some(0))
+                } else {
+                    let rid = request::issue(
+                        context.pid(),
+                        context.tid(),
+                        KernelRequestKind::Sleep,
+                    );
+                    context.sleep(deadline, rid);
+                    context.thread().state = ExecutionState::Pending(rid);
+                    Ok(None)
+                }
+            }
             SystemCall::Access => {
                 let address: Address = arg0;
                 let length: usize = arg1;
@@ -321,10 +822,7 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                         FilesystemAbstractLayerError::ForeignMountPoint(
                                             _rem,
                                             _mid,
-                                        ) => {
-                                            todo!();
-                                            Ok(None)
-                                        }
+                                        ) => Err(SystemCallError::NotSupported),
                                         _ => Err(SystemCallError::InternalError),
                                     },
                                 }
@@ -358,16 +856,24 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                             // 写入对象必须按 8 对齐， DentryObject 已经保证 8 byte 对齐了，就差 name 了。
                                             let name_length = (s.len() + 8 - 1) & !(8 - 1);
                                             if copied + size + name_length <= buffer_length {
-                                                process.write(buffer_address + copied, unsafe{
-                                                    from_raw_parts(d as *const DentryObject as *const u8, size)
-                                                }, size).expect("return user an error if failed or kill process");
-                                                process
-                                                    .write(
-                                                        buffer_address + copied + size,
-                                                        s.as_bytes(),
-                                                        s.len(),
+                                                let object = unsafe {
+                                                    from_raw_parts(
+                                                        d as *const DentryObject as *const u8,
+                                                        size,
                                                     )
-                                                    .expect("error!");
+                                                };
+                                                if let Err(err) = process
+                                                    .write(buffer_address + copied, object, size)
+                                                    .and_then(|_| {
+                                                        process.write(
+                                                            buffer_address + copied + size,
+                                                            s.as_bytes(),
+                                                            s.len(),
+                                                        )
+                                                    })
+                                                {
+                                                    return Err(err.into());
+                                                }
                                                 copied += size + name_length;
                                                 count += 1;
                                             } else {
@@ -390,10 +896,7 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                         FilesystemAbstractLayerError::ForeignMountPoint(
                                             _rem,
                                             _mid,
-                                        ) => {
-                                            todo!();
-                                            Ok(None)
-                                        }
+                                        ) => Err(SystemCallError::NotSupported),
                                         _ => Err(SystemCallError::InternalError),
                                     },
                                 }
@@ -431,10 +934,7 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                                 FilesystemAbstractLayerError::ForeignMountPoint(
                                                     _rem,
                                                     _mid,
-                                                ) => {
-                                                    todo!();
-                                                    Ok(None)
-                                                }
+                                                ) => Err(SystemCallError::NotSupported),
                                                 _ => Err(SystemCallError::InternalError),
                                             },
                                         }
@@ -486,10 +986,7 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                         FilesystemAbstractLayerError::ForeignMountPoint(
                                             _rem,
                                             _mid,
-                                        ) => {
-                                            todo!();
-                                            Ok(None)
-                                        }
+                                        ) => Err(SystemCallError::NotSupported),
                                         _ => Err(SystemCallError::InternalError),
                                     },
                                 }
@@ -528,10 +1025,7 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                             FilesystemAbstractLayerError::ForeignMountPoint(
                                                 _rem,
                                                 _mid,
-                                            ) => {
-                                                todo!();
-                                                Ok(None)
-                                            }
+                                            ) => Err(SystemCallError::NotSupported),
                                             _ => Err(SystemCallError::InternalError),
                                         },
                                     },
@@ -552,21 +1046,44 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                 let kind = arg1;
                 let buffer_address = arg2 as Address;
                 let buffer_length = arg3;
+                let blocking = arg4 != 0;
                 let mut error: Option<SystemCallError> = None;
-                if context.find(target, |to| {
+                let mut pending: Option<Rid> = None;
+                let found = context.find(target, |to| {
+                    if to.health != ProcessHealth::Healthy {
+                        error = Some(SystemCallError::ObjectNotFound);
+                        return;
+                    }
                     match process.read(buffer_address, buffer_length) {
                         Ok(buffer) => {
                             let msg = Message::new(context.pid(), kind, buffer);
-                            if !to.mailbox.put(msg) {
-                                error = Some(SystemCallError::ObjectNotAvailable)
+                            if let Err(err) = to.mailbox.put(msg) {
+                                if blocking {
+                                    // 持有对方的锁时登记，对方取走消息后再唤醒就不会错过
+                                    pending = Some(request::issue(
+                                        context.pid(),
+                                        context.tid(),
+                                        KernelRequestKind::MailboxSpace(target),
+                                    ));
+                                } else {
+                                    error = Some(err.into());
+                                }
                             }
                         }
                         Err(err) => error = Some(err.into()),
                     }
-                }) {
-                    if let Some(err) = error {
+                });
+                if found {
+                    if let Some(rid) = pending {
+                        context.thread().state = ExecutionState::Pending(rid);
+                        Ok(None)
+                    } else if let Some(err) = error {
                         Err(err)
                     } else {
+                        // 唤醒对方阻塞在 Peek 上的线程
+                        request::resolve(|r| {
+                            r.pid() == target && r.kind() == KernelRequestKind::MessageArrival
+                        });
                         Ok(Some(0))
                     }
                 } else {
@@ -576,12 +1093,14 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
             SystemCall::Peek => {
                 let buffer_address = arg0 as Address;
                 let buffer_length = arg1;
+                let blocking = arg2 != 0;
                 if buffer_length == size_of::<MessageDigest>() {
                     let thread = context.thread();
                     if thread.mailbox.available() {
                         if let Some(message) = process.mailbox.take() {
                             let digest = message.digest();
-                            thread.mailbox.put(message);
+                            let _ = thread.mailbox.put(message);
+                            Self::notify_mailbox_space(context.pid());
                             let bytes = unsafe {
                                 from_raw_parts(
                                     (&digest as *const MessageDigest) as *const u8,
@@ -592,6 +1111,15 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                                 Ok(_) => Ok(Some(1)),
                                 Err(err) => return Err(err.into()),
                             }
+                        } else if blocking {
+                            // 投递方需要拿到本进程的 state_lock 才能放入邮箱，登记完请求之前不会有消息溜进来
+                            let rid = request::issue(
+                                context.pid(),
+                                context.tid(),
+                                KernelRequestKind::MessageArrival,
+                            );
+                            thread.state = ExecutionState::Pending(rid);
+                            Ok(None)
                         } else {
                             Err(SystemCallError::ObjectNotAvailable)
                         }
@@ -602,6 +1130,115 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                     Err(SystemCallError::IllegalArgument)
                 }
             }
+            SystemCall::Discard => {
+                let all = arg0 != 0;
+                let mut count = 0usize;
+                if let Some(message) = context.thread().mailbox.take() {
+                    Self::refuse(context.pid(), &message);
+                    count += 1;
+                }
+                if all {
+                    for message in process.mailbox.clear() {
+                        Self::refuse(context.pid(), &message);
+                        count += 1;
+                    }
+                    Self::notify_mailbox_space(context.pid());
+                }
+                Ok(Some(count))
+            }
+            SystemCall::Call => {
+                let target = arg0 as Pid;
+                let kind = arg1;
+                let buffer_address = arg2 as Address;
+                let buffer_length = arg3;
+                let reply_address = arg4 as Address;
+                let reply_length = arg5;
+                if let Some(mut request) = fed {
+                    // 被回复唤醒，把回复搬进调用方的缓冲区，放不下的部分截断，返回回复的完整长度
+                    return if let Some(response) = request.take_response() {
+                        let length = if response.len() > reply_length {
+                            reply_length
+                        } else {
+                            response.len()
+                        };
+                        match process.write(reply_address, &response, length) {
+                            Ok(_) => Ok(Some(response.len())),
+                            Err(err) => Err(err.into()),
+                        }
+                    } else {
+                        // 对方退出或者丢弃了消息
+                        Err(SystemCallError::ObjectNotAccessible)
+                    };
+                }
+                let rid = request::issue(
+                    context.pid(),
+                    context.tid(),
+                    KernelRequestKind::Reply(target),
+                );
+                let mut error: Option<SystemCallError> = None;
+                let found = context.find(target, |to| {
+                    if to.health != ProcessHealth::Healthy {
+                        error = Some(SystemCallError::ObjectNotFound);
+                        return;
+                    }
+                    match process.read(buffer_address, buffer_length) {
+                        Ok(buffer) => {
+                            let msg = Message::with_reply(context.pid(), kind, buffer, rid);
+                            if let Err(err) = to.mailbox.put(msg) {
+                                error = Some(err.into());
+                            }
+                        }
+                        Err(err) => error = Some(err.into()),
+                    }
+                });
+                if !found {
+                    request::cancel(rid);
+                    Err(SystemCallError::ObjectNotFound)
+                } else if let Some(err) = error {
+                    request::cancel(rid);
+                    Err(err)
+                } else {
+                    request::resolve(|r| {
+                        r.pid() == target && r.kind() == KernelRequestKind::MessageArrival
+                    });
+                    context.thread().state = ExecutionState::Pending(rid);
+                    Ok(None)
+                }
+            }
+            SystemCall::Reply => {
+                let token = arg0 as Rid;
+                let buffer_address = arg1 as Address;
+                let buffer_length = arg2;
+                let pid = context.pid();
+                match process.read(buffer_address, buffer_length) {
+                    // 回复凭据只对收到这条消息的进程有效，且只能用一次
+                    Ok(payload) => {
+                        if request::answer(
+                            token,
+                            |r| r.kind() == KernelRequestKind::Reply(pid),
+                            payload,
+                        ) {
+                            Ok(Some(0))
+                        } else {
+                            Err(SystemCallError::ObjectNotFound)
+                        }
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
+            SystemCall::Reserve => {
+                let capacity = arg0;
+                let quota = arg1;
+                if capacity == 0 || quota == 0 || quota > capacity {
+                    Err(SystemCallError::IllegalArgument)
+                } else if capacity > MAILBOX_CAPACITY_LIMIT {
+                    Err(SystemCallError::ReachLimit)
+                } else {
+                    process.mailbox.configure(capacity, quota);
+                    Self::notify_mailbox_space(context.pid());
+                    Ok(Some(capacity))
+                }
+            }
             SystemCall::Receive => {
                 let buffer_address = arg0 as Address;
                 let buffer_length = arg1;
@@ -619,7 +1256,150 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                     Err(SystemCallError::ObjectNotAvailable)
                 }
             }
-            _ => unimplemented!("unimplemented syscall: {:?}", call),
+            _ => Err(SystemCallError::FunctionNotAvailable),
+        }
+    }
+
+    fn exit(context: &S::Context, code: ExitCode) {
+        Self::terminate(context, context.pid(), code);
+    }
+
+    // 结束任意一个进程，对方正在别的核心上运行也没关系，它的线程回到内核时会被调度走。已经结束的返回 false
+    fn terminate(context: &S::Context, pid: Pid, code: ExitCode) -> bool {
+        let mut alive = false;
+        context.find(pid, |target| {
+            if target.health == ProcessHealth::Healthy {
+                target.health = ProcessHealth::Dead(code);
+                alive = true;
+            }
+        });
+        if !alive {
+            return false;
+        }
+        let parent = context.parent_of(pid).unwrap_or(pid);
+        debug!("app.exit Pid={} exited with code {}", pid, code);
+        Self::release_tunnels(context, pid);
+        // 阻塞在往这里投递的发送方重新尝试时会得知目标已经不在了，等待回复的调用方也不会再等到了
+        Self::notify_mailbox_space(pid);
+        request::resolve(|r| r.kind() == KernelRequestKind::Reply(pid));
+        // 已经退出但还没被收集的子进程不会再有人等了，直接回收
+        for (child, health) in context.children_of(pid) {
+            if let ProcessHealth::Dead(_) = health {
+                Self::reap(context, child);
+            }
+        }
+        let mut notified = false;
+        if parent != pid {
+            context.find(parent, |target| {
+                if target.health == ProcessHealth::Healthy {
+                    target.signal.enqueue(SystemSignal::ChildExit as SignalMap);
+                    notified = true;
+                }
+            });
+        }
+        if notified {
+            // 留给父进程 Wait 收集退出码，顺便唤醒正在等它的线程
+            request::resolve(|r| {
+                r.pid() == parent
+                    && match r.kind() {
+                        KernelRequestKind::ChildExit(target) => target == 0 || target == pid,
+                        _ => false,
+                    }
+            });
+        } else {
+            // 没有父进程可以收集，自己回收自己
+            Self::reap(context, pid);
+        }
+        true
+    }
+
+    // 丢弃等待回复的消息时叫醒调用方，让它得知不会有回复了
+    fn refuse(pid: Pid, message: &Message) {
+        let token = message.reply();
+        if token != 0 {
+            request::resolve(|r| r.id() == token && r.kind() == KernelRequestKind::Reply(pid));
+        }
+    }
+
+    // 邮箱腾出了位置，唤醒阻塞在往这里投递的发送方
+    fn notify_mailbox_space(pid: Pid) {
+        request::resolve(|r| r.kind() == KernelRequestKind::MailboxSpace(pid));
+    }
+
+    // 中断隧道上 pid 的对端：有线程在等就直接唤醒，否则留下铃，返回还需要发信号的对端
+    fn interrupt_peer(tunnel: &mut Tunnel, pid: Pid) -> Option<Pid> {
+        let key = tunnel.key();
+        if let Some(peer) = tunnel.peer(pid)
+            && request::resolve(|r| {
+                r.pid() == peer && r.kind() == KernelRequestKind::TunnelInterrupt(key)
+            }) == 0
+        {
+            tunnel.ring(peer);
+            Some(peer)
+        } else {
+            None
+        }
+    }
+
+    // 会锁对端进程，调用前先放掉 TUNNELS，对端可能正拿着自己的锁等 TUNNELS
+    fn signal_tunnel_ready(context: &S::Context, peer: Pid, key: usize) {
+        context.find(peer, |target| {
+            let signal = SystemSignal::TunnelReady as SignalMap;
+            if target.health == ProcessHealth::Healthy {
+                target.signal.enqueue_with(signal, (key, 0));
+            }
+        });
+    }
+
+    // 进程死亡时断开它持有的所有隧道并通知还在的对端，没人持有的隧道连同页帧一起释放
+    // 目标可能是别的进程，拿着 TUNNELS 的时候不能去锁它
+    fn release_tunnels(context: &S::Context, pid: Pid) {
+        let mut keys: Vec<usize> = Vec::new();
+        context.find(pid, |target| keys = target.tunnel_keys());
+        let mut unmapped: Vec<(PageNumber, usize)> = Vec::new();
+        let mut signaled: Vec<(Pid, usize)> = Vec::new();
+        let mut tunnels = TUNNELS.lock();
+        for key in keys.iter().copied() {
+            if let Some(index) = tunnels.iter().position(|t| t.key() == key) {
+                let tunnel = &mut tunnels[index];
+                let count = tunnel.page_count();
+                if let Some(peer) = Self::interrupt_peer(tunnel, pid)
+                    && peer != pid
+                {
+                    signaled.push((peer, key));
+                }
+                let mut delete = false;
+                // 两端都是自己的话要断两次
+                while let Some((last, number)) = tunnel.unlink(pid) {
+                    unmapped.push((number, count));
+                    delete |= last;
+                }
+                if delete {
+                    tunnels.swap_remove(index);
+                }
+            }
+        }
+        // 建好了还没有人连上的
+        tunnels.retain(|t| !(t.owner == pid && t.first.is_none()));
+        drop(tunnels);
+        context.find(pid, |target| {
+            for (number, count) in unmapped.iter() {
+                let _ = target.free(*number, *count);
+            }
+            for key in keys.iter() {
+                target.tunnel_eject(*key);
+            }
+        });
+        for (peer, key) in signaled {
+            Self::signal_tunnel_ready(context, peer, key);
+        }
+    }
+
+    // 回收已经退出的进程：撤销它的请求，再从进程表里摘除。隧道在死亡时已经断开，进程的内存和邮箱随进程一起释放
+    fn reap(context: &S::Context, pid: Pid) {
+        request::cancel_all(pid);
+        if context.remove_proc(pid) {
+            debug!("app.reap Pid={} removed", pid);
         }
     }
 
@@ -633,7 +1413,7 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
             TrapCause::Breakpoint => {
                 // for debugger
                 self.scheduler.with_context(|ctx| {
-                    ctx.process().health = ProcessHealth::Dead(-0x114514);
+                    Self::exit(ctx, -0x114514);
                     println!(
                         "#{} Pid={} Tid={} requested a breakpoint",
                         self.id,
@@ -644,54 +1424,59 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                 self.scheduler.schedule();
             }
             TrapCause::PageFault(address, op) => {
-                if let Some(region) = self.scheduler.is_address_in(address) {
-                    match region {
-                        ProcessAddressRegion::Stack(_) => {
-                            self.scheduler.with_context(|ctx| {
-                                ctx.process()
-                                    .fill(
-                                        address >> PAGE_BITS,
-                                        1,
-                                        MemoryRegionAttribute::Write | MemoryRegionAttribute::Read,
-                                        false,
-                                    )
-                                    .expect("fill stack failed, to be killed");
-                            });
-                        }
-                        ProcessAddressRegion::TrapFrame(_) => {
-                            self.scheduler.with_context(|ctx| {
-                                ctx.process()
-                                    .fill(
-                                        address >> PAGE_BITS,
-                                        1,
-                                        MemoryRegionAttribute::Write | MemoryRegionAttribute::Read,
-                                        true,
-                                    )
-                                    .expect("fill trapframe failed, to be killed");
-                            });
-                        }
-                        _ => self.scheduler.with_context(|ctx| {
-                            todo!(
-                                "unexpected {:?} memory page fault at: {:#x}, dump: \n{}",
-                                op,
-                                address,
-                                ctx.trapframe()
-                            )
-                        }),
-                    }
-                } else {
-                    unreachable!(
-                        "previous process triggered page fault while it is not in current field"
-                    )
+                let filled = match self.scheduler.is_address_in(address) {
+                    Some(region) => self.fill(address, region, &op),
+                    None => false,
+                };
+                if !filled {
+                    debug!("#{} {:?} page fault at {:#x}", self.id, op, address);
+                    self.fault(address, EXIT_CODE_SEGMENTATION_FAULT);
                 }
             }
+            TrapCause::AccessFault(address, op) => {
+                debug!("#{} {:?} access fault at {:#x}", self.id, op, address);
+                self.fault(address, EXIT_CODE_SEGMENTATION_FAULT);
+            }
+            TrapCause::Misaligned(address, op) => {
+                debug!("#{} {:?} misaligned at {:#x}", self.id, op, address);
+                self.fault(address, EXIT_CODE_MISALIGNED);
+            }
+            TrapCause::IllegalInstruction(instruction) => {
+                debug!("#{} illegal instruction {:#x}", self.id, instruction);
+                // stval 里是出错指令的编码，和缺页时的地址一样作为第一个参数交给处理函数
+                self.fault(instruction, EXIT_CODE_ILLEGAL_INSTRUCTION);
+            }
             TrapCause::EnvironmentCall => self.scheduler.with_context(|ctx| {
-                let trapframe = ctx.trapframe();
+                let Some(trapframe) = ctx.trapframe() else {
+                    // 线程能陷入就说明 TrapFrame 在，这里只是以防万一
+                    Self::exit(ctx, EXIT_CODE_KILLED);
+                    return;
+                };
                 // 只有同步调用才会前进下一个指令
                 // `handle_system_call` 返回 Ok(SystemCallProcedureResult)，包含 Finished(usize) 和 Pending()，后者会切换到其他进程
-                let mut syscall = trapframe
-                    .extract_syscall()
-                    .expect("invalid sys call triggered");
+                let mut syscall = if let Some(syscall) = trapframe.extract_syscall() {
+                    syscall
+                } else {
+                    // 不认识的调用号同样作为调用失败返回
+                    trapframe.write_error(SystemCallError::FunctionNotAvailable);
+                    trapframe.move_next_instruction();
+                    return;
+                };
+                let thread = ctx.thread();
+                if thread.state == ExecutionState::Dead
+                    || ctx.process().health != ProcessHealth::Healthy
+                {
+                    // 在其他核心上被杀掉的线程或者进程，不再处理它的调用
+                    ctx.schedule();
+                    return;
+                }
+                // 被唤醒后以相同参数重新执行该调用，原来的请求交给调用处理取走它带回的数据
+                let fed = if let ExecutionState::Fed(rid) = thread.state {
+                    thread.state = ExecutionState::Running;
+                    request::take(rid)
+                } else {
+                    None
+                };
                 match Self::handle_system_call(
                     ctx,
                     syscall.call,
@@ -699,6 +1484,9 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                     syscall.arg1,
                     syscall.arg2,
                     syscall.arg3,
+                    syscall.arg4,
+                    syscall.arg5,
+                    fed,
                     &mut self.random,
                 ) {
                     // Some 为同步调用，立即返回结果
@@ -715,10 +1503,206 @@ impl<S: Scheduler, R: RandomGenerator> ApplicationHart<S, R> {
                 }
             }),
             _ => {
-                todo!("unimplemented trap cause {:?}", cause)
+                debug!("#{} unexpected trap cause {:?}", self.id, cause);
+                self.fault(0, EXIT_CODE_KILLED);
             }
         }
     }
+
+    // 懒分配栈、TrapFrame、堆和程序里全零的页，写到和父子进程共享的页上时复制一份，失败了当作访问了坏地址
+    fn fill(
+        &mut self,
+        address: Address,
+        region: ProcessAddressRegion,
+        op: &MemoryOperation,
+    ) -> bool {
+        let mut filled = false;
+        self.scheduler.with_context(|ctx| {
+            let process = ctx.process();
+            // 页已经在了说明是权限不对，再填一次还是会错，除非是写时复制的页或者本核心的 TLB 过时了。
+            // TrapFrame 对用户态一直可写却不可访问，不能当成过时的 TLB，不然会一直出错
+            if process.translate(address).is_some() {
+                filled = match region {
+                    ProcessAddressRegion::TrapFrame(_) | ProcessAddressRegion::Invalid => false,
+                    _ => {
+                        (matches!(op, MemoryOperation::Write) && process.copy_on_write(address))
+                            || process.is_stale_fault(address, op)
+                    }
+                };
+                return;
+            }
+            filled = match region {
+                // 栈要记到栈的用量里，超过上限就当作越界
+                ProcessAddressRegion::Stack(_) => {
+                    process.fill_stack(address >> PAGE_BITS, 1).is_ok()
+                }
+                ProcessAddressRegion::TrapFrame(_) => process
+                    .fill(
+                        address >> PAGE_BITS,
+                        1,
+                        MemoryRegionAttribute::Write | MemoryRegionAttribute::Read,
+                        true,
+                    )
+                    .is_ok(),
+                ProcessAddressRegion::Heap | ProcessAddressRegion::Program => {
+                    process.fill_lazy(address)
+                }
+                _ => false,
+            };
+        });
+        filled
+    }
+
+    // 用户态异常：处理得了就转成信号交给进程自己处理，否则带着异常对应的退出码结束进程，内核不受影响
+    fn fault(&mut self, address: Address, code: ExitCode) {
+        self.scheduler.with_context(|ctx| {
+            let pc = ctx.trapframe().map_or(0, |t| t.pc as Address);
+            let signal = SystemSignal::Fault as SignalMap;
+            let process = ctx.process();
+            let catchable = process.signal.is_accepted(signal)
+                && !process.signal.is_blocked(signal)
+                && !(ctx.tid() == 0 && process.signal.is_handling());
+            if catchable {
+                process.signal.enqueue_with(signal, (address, pc));
+                // 处理函数在主线程上运行，出错的子线程回不去了
+                if ctx.tid() != 0 {
+                    ctx.kill_thread(ctx.tid(), code);
+                }
+            } else {
+                println!(
+                    "#{} Pid={} Tid={} killed by fault at pc={:#x}, address={:#x}",
+                    self.id,
+                    ctx.pid(),
+                    ctx.tid(),
+                    pc,
+                    address
+                );
+                Self::exit(ctx, code);
+            }
+        });
+        self.scheduler.schedule();
+    }
+}
+
+fn required_permission(call: SystemCall) -> Option<ProcessPermission> {
+    match call {
+        // 只有拥有全部权限的进程才能让内核崩溃
+        SystemCall::Die => Some(ProcessPermission::All),
+        SystemCall::Fork | SystemCall::ExecuteBytes | SystemCall::ExecuteFile => {
+            Some(ProcessPermission::Process)
+        }
+        SystemCall::MapPhysical => Some(ProcessPermission::Memory),
+        SystemCall::Mount | SystemCall::Unmount => Some(ProcessPermission::Service),
+        _ => None,
+    }
+}
+
+fn inherit_permissions(
+    parent: &Process,
+    requested: usize,
+) -> Result<FlagSet<ProcessPermission>, SystemCallError> {
+    if requested == PERMISSIONS_INHERITED {
+        parent.inherit_permissions(None).map_err(|e| e.into())
+    } else if let Ok(flags) = FlagSet::<ProcessPermission>::new(requested as u32) {
+        parent.inherit_permissions(Some(flags)).map_err(|e| e.into())
+    } else {
+        Err(SystemCallError::IllegalArgument)
+    }
+}
+
+fn page_count(length: usize) -> Result<usize, SystemCallError> {
+    match length.checked_add(PAGE_SIZE - 1) {
+        Some(rounded) if length > 0 => Ok(rounded >> PAGE_BITS),
+        _ => Err(SystemCallError::IllegalArgument),
+    }
+}
+
+// 没有属性的页表项会被当成下一级页表，只写不读是保留组合
+fn map_attributes(bits: usize) -> Result<FlagSet<MemoryRegionAttribute>, SystemCallError> {
+    match FlagSet::<MemoryRegionAttribute>::new(bits) {
+        Ok(attributes)
+            if !attributes.is_empty()
+                && !(attributes.contains(MemoryRegionAttribute::Write)
+                    && !attributes.contains(MemoryRegionAttribute::Read)) =>
+        {
+            Ok(attributes)
+        }
+        _ => Err(SystemCallError::IllegalArgument),
+    }
+}
+
+// 读出 StartupArguments 描述的参数、环境变量和内存上限，地址为 0 表示都没有，上限全部继承
+fn read_startup(
+    process: &mut Process,
+    address: Address,
+) -> Result<(Vec<String>, Vec<String>, MemoryLimit), SystemCallError> {
+    if address == 0 {
+        return Ok((Vec::new(), Vec::new(), MemoryLimit::INHERITED));
+    }
+    let description = read_words(
+        process,
+        address,
+        size_of::<StartupArguments>() / size_of::<usize>(),
+    )?;
+    let mut budget = STARTUP_LIMIT;
+    let arguments = read_strings(process, description[0], description[1], &mut budget, false)?;
+    let variables = read_strings(process, description[2], description[3], &mut budget, true)?;
+    let limit = MemoryLimit {
+        page: description[4],
+        heap: description[5],
+        stack: description[6],
+    };
+    Ok((arguments, variables, limit))
+}
+
+// 表和字符串都算进上限，读之前先扣，数量和长度都是用户随便填的
+fn read_strings(
+    process: &mut Process,
+    table: Address,
+    count: usize,
+    budget: &mut usize,
+    variable: bool,
+) -> Result<Vec<String>, SystemCallError> {
+    let table_size = count
+        .checked_mul(2 * size_of::<usize>())
+        .filter(|s| *s <= *budget)
+        .ok_or(SystemCallError::IllegalArgument)?;
+    *budget -= table_size;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let words = read_words(process, table, count * 2)?;
+    let mut strings = Vec::with_capacity(count);
+    for pair in words.chunks_exact(2) {
+        let (address, length) = (pair[0], pair[1]);
+        if length > *budget {
+            return Err(SystemCallError::IllegalArgument);
+        }
+        *budget -= length;
+        let bytes = match process.read(address, length) {
+            Ok(bytes) => bytes,
+            Err(err) => return Err(err.into()),
+        };
+        match String::from_utf8(bytes) {
+            Ok(string) if !variable || string.contains('=') => strings.push(string),
+            _ => return Err(SystemCallError::IllegalArgument),
+        }
+    }
+    Ok(strings)
+}
+
+fn read_words(
+    process: &mut Process,
+    address: Address,
+    count: usize,
+) -> Result<Vec<usize>, SystemCallError> {
+    match process.read(address, count * size_of::<usize>()) {
+        Ok(bytes) => Ok(bytes
+            .chunks_exact(size_of::<usize>())
+            .map(|c| usize::from_ne_bytes(c.try_into().unwrap()))
+            .collect()),
+        Err(err) => Err(err.into()),
+    }
 }
 
 pub fn awake_idle() -> bool {
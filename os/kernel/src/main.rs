@@ -4,7 +4,7 @@
 
 use core::{arch::global_asm, slice::from_raw_parts};
 
-use alloc::format;
+use alloc::{format, vec, vec::Vec};
 use erhino_shared::{
     fal::{DentryAttribute, DentryType},
     path::Path,
@@ -14,6 +14,7 @@ use tar_no_std::TarArchiveRef;
 use crate::{
     board::this_board,
     hart::SchedulerImpl,
+    mm::page::{paging_mode, PageEntryImpl, PageTableEntry},
     task::{proc::Process, sched::Scheduler},
 };
 
@@ -44,6 +45,11 @@ pub fn main() {
     for cpu in board.map().cpus() {
         println!("[Hart #{}] {:?}@{:#x}", cpu.id(), cpu.mmu(), cpu.freq());
     }
+    println!(
+        "[Paging ] {:?}, user space {:#x}",
+        paging_mode(),
+        PageEntryImpl::space_size()
+    );
     println!(
         "[IntrCtr] @{:#x}({:#x})",
         board.map().intrc().address(),
@@ -79,8 +85,9 @@ pub fn main() {
             )
             .unwrap();
             if file.filename().starts_with("bin/") {
-                let process = Process::from_elf(file.data()).unwrap();
-                SchedulerImpl::add(process, None);
+                let mut process = Process::from_elf(file.data()).unwrap();
+                process.set_startup(vec![format!("/boot/{}", file.filename())], Vec::new());
+                SchedulerImpl::add(process, None).expect("no memory for initial processes");
             }
         }
         println!("\x1b[0;32m=LINK^START=\x1b[0m");
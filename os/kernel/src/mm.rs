@@ -1,14 +1,17 @@
-use core::cell::OnceCell;
+use core::{arch::asm, cell::OnceCell};
 
 use erhino_shared::proc::Tid;
 
 use crate::{
+    board::{self, device::cpu::MmuType},
     external::{_memory_end, _memory_start, _user_trap},
+    hart,
     mm::page::{PageEntryFlag, PageTableEntry, PAGE_BITS},
 };
 
 use self::{page::PageEntryImpl, unit::MemoryUnit};
 
+pub mod asid;
 pub mod frame;
 pub mod page;
 pub mod unit;
@@ -31,10 +34,45 @@ pub enum ProcessAddressRegion {
 }
 
 pub fn init() {
+    // 所有应用核心（和 hart::init 启动的是同一批）都支持的最宽的模式。
+    // 设备树可能说得比实际的宽，在启动核心上装不上就退到窄一级
+    let widest = board::this_board()
+        .map()
+        .cpus()
+        .iter()
+        .filter(|cpu| hart::is_application(cpu))
+        .map(|cpu| cpu.mmu())
+        .min()
+        .unwrap_or(MmuType::Sv39);
+    let mut unit: Option<KernelUnit> = None;
+    for mode in [MmuType::Sv57, MmuType::Sv48, MmuType::Sv39] {
+        if mode > widest {
+            continue;
+        }
+        page::select_paging_mode(mode);
+        let built = build_kernel_unit();
+        if mode == MmuType::Sv39 || is_satp_accepted(built.satp()) {
+            unit = Some(built);
+            break;
+        }
+    }
+    let unit = unit.expect("no paging mode available");
+    // kernel has no trap frame so it has no trap frame mapped
+    let satp = unit.satp();
+    unsafe {
+        if let Err(_) = KERNEL_UNIT.set(unit) {
+            panic!("set KERNEL_UNIT shared data failed")
+        }
+        KERNEL_SATP = satp;
+    }
+    asid::init();
+}
+
+fn build_kernel_unit() -> KernelUnit {
     // NOTE: 有些实现要求 PTE 的 AD 位在访问前得是 1 否则会触发 page fault。内核必须设置 AD 强制全为 1。
     let memory_start = _memory_start as usize >> PAGE_BITS;
     let memory_end = _memory_end as usize >> PAGE_BITS;
-    let mut unit = MemoryUnit::<PageEntryImpl>::new(0).unwrap();
+    let mut unit = MemoryUnit::<PageEntryImpl>::new().unwrap();
     // mmio device space
     unit.map(0x0, 0x0, memory_start, PageEntryFlag::PrefabKernelDevice)
         .expect("map mmio device failed");
@@ -54,12 +92,21 @@ pub fn init() {
         PageEntryFlag::PrefabKernelTrampoline,
     )
     .expect("map kernel trampoline failed");
-    // kernel has no trap frame so it has no trap frame mapped
-    let satp = unit.satp();
+    unit
+}
+
+// 不支持的模式写进 satp 会被整个忽略。内核空间是恒等映射，试着开启分页没有影响
+fn is_satp_accepted(satp: usize) -> bool {
+    let written: usize;
     unsafe {
-        if let Err(_) = KERNEL_UNIT.set(unit) {
-            panic!("set KERNEL_UNIT shared data failed")
-        }
-        KERNEL_SATP = satp;
+        asm!(
+            "csrw satp, {satp}",
+            "csrr {written}, satp",
+            "csrw satp, zero",
+            "sfence.vma",
+            satp = in(reg) satp,
+            written = out(reg) written
+        );
     }
+    written >> 60 == satp >> 60
 }
@@ -0,0 +1,154 @@
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use erhino_shared::{mem::PageNumber, sync::spin::SimpleLock};
+use lock_api::Mutex;
+
+use crate::{hart::HartId, sbi};
+
+use super::{page::PAGE_BITS, KERNEL_SATP};
+
+// identity = 代 << 16 | ASID。代从 1 开始，identity 为 0 的是内核和还没运行过的单元
+const ASID_BITS: usize = 16;
+const ASID_MASK: usize = (1 << ASID_BITS) - 1;
+const SATP_ASID_OFFSET: usize = 44;
+// 超过这么多页就不一页一页地刷，直接刷掉整个 ASID
+const FLUSH_THRESHOLD: usize = 64;
+const MAX_HARTS: usize = usize::BITS as usize;
+
+// 实现支持的 ASID 个数，包括留给内核的 0。为 1 时大家都用 0，切换页表时整个刷掉
+static mut ASID_LIMIT: usize = 1;
+// (当前代, 下一个没分出去的 ASID)
+static ALLOCATOR: Mutex<SimpleLock, (usize, usize)> = Mutex::new((1, 1));
+static GENERATION: AtomicUsize = AtomicUsize::new(1);
+// 每个核心上一次清空 TLB 时的代，核心只在自己身上写
+static FLUSHED: [AtomicUsize; MAX_HARTS] = [const { AtomicUsize::new(0) }; MAX_HARTS];
+
+pub fn init() {
+    // 往 ASID 字段写全 1 再读回来，实现支持几位就留下几位。内核空间是恒等映射，临时开启分页没有影响
+    let probe = unsafe { KERNEL_SATP } | (ASID_MASK << SATP_ASID_OFFSET);
+    let written: usize;
+    unsafe {
+        asm!(
+            "csrw satp, {probe}",
+            "csrr {written}, satp",
+            "csrw satp, zero",
+            "sfence.vma",
+            probe = in(reg) probe,
+            written = out(reg) written
+        );
+        ASID_LIMIT = 1 << ((written >> SATP_ASID_OFFSET) & ASID_MASK).count_ones();
+    }
+}
+
+pub fn asid_of(identity: usize) -> usize {
+    identity & ASID_MASK
+}
+
+pub fn generation_of(identity: usize) -> usize {
+    identity >> ASID_BITS
+}
+
+pub fn satp_field_of(identity: usize) -> usize {
+    asid_of(identity) << SATP_ASID_OFFSET
+}
+
+// 单元的 ASID 是上一代分的就重新分一个，分完了开启新的一代。返回当前的 identity
+pub fn assign(identity: &AtomicUsize) -> usize {
+    let current = identity.load(Ordering::Acquire);
+    if generation_of(current) == GENERATION.load(Ordering::Acquire) {
+        return current;
+    }
+    let mut state = ALLOCATOR.lock();
+    let (generation, next) = &mut *state;
+    // 可能在等锁的时候被别的核心分好了
+    let current = identity.load(Ordering::Acquire);
+    if generation_of(current) == *generation {
+        return current;
+    }
+    let limit = unsafe { ASID_LIMIT };
+    let asid = if limit <= 1 {
+        0
+    } else {
+        if *next >= limit {
+            *generation += 1;
+            *next = 1;
+            GENERATION.store(*generation, Ordering::Release);
+        }
+        *next += 1;
+        *next - 1
+    };
+    let assigned = (*generation << ASID_BITS) | asid;
+    identity.store(assigned, Ordering::Release);
+    assigned
+}
+
+// 核心第一次运行新一代的 ASID 之前清空整个 TLB，上一代留下的项可能和新分配的撞号
+pub fn synchronize(hart: HartId, identity: usize) {
+    let generation = generation_of(identity);
+    if FLUSHED[hart].fetch_max(generation, Ordering::AcqRel) < generation {
+        unsafe { asm!("sfence.vma") }
+    }
+}
+
+// harts 是运行过这个单元的核心，换代后也不清掉：还没清空过这一代的核心上可能还在用上一代分给这个单元的 ASID，
+// 不知道是哪个，只能不分 ASID 地刷
+pub fn flush(harts: usize, local: HartId, identity: usize, vpn: PageNumber, count: usize) {
+    let generation = generation_of(identity);
+    let asid = asid_of(identity);
+    let mut tagged = 0usize;
+    let mut untagged = 0usize;
+    for hart in 0..MAX_HARTS {
+        if harts & (1 << hart) != 0 {
+            if FLUSHED[hart].load(Ordering::Acquire) >= generation {
+                tagged |= 1 << hart;
+            } else {
+                untagged |= 1 << hart;
+            }
+        }
+    }
+    let whole = count > FLUSH_THRESHOLD;
+    if tagged & (1 << local) != 0 {
+        tagged &= !(1 << local);
+        flush_local(Some(asid), vpn, count, whole);
+    } else if untagged & (1 << local) != 0 {
+        untagged &= !(1 << local);
+        flush_local(None, vpn, count, whole);
+    }
+    let (start, size) = if whole {
+        (0, usize::MAX)
+    } else {
+        (vpn << PAGE_BITS, count << PAGE_BITS)
+    };
+    if tagged != 0 {
+        let _ = sbi::remote_sfence_vma_asid(tagged, 0, start, size, asid);
+    }
+    if untagged != 0 {
+        let _ = sbi::remote_sfence_vma(untagged, 0, start, size);
+    }
+}
+
+pub fn flush_local(asid: Option<usize>, vpn: PageNumber, count: usize, whole: bool) {
+    unsafe {
+        match (asid, whole) {
+            (Some(id), true) => asm!("sfence.vma zero, {asid}", asid = in(reg) id),
+            (None, true) => asm!("sfence.vma"),
+            (Some(id), false) => {
+                for page in vpn..(vpn + count) {
+                    asm!(
+                        "sfence.vma {addr}, {asid}",
+                        addr = in(reg) page << PAGE_BITS,
+                        asid = in(reg) id
+                    )
+                }
+            }
+            (None, false) => {
+                for page in vpn..(vpn + count) {
+                    asm!("sfence.vma {addr}, zero", addr = in(reg) page << PAGE_BITS)
+                }
+            }
+        }
+    }
+}
@@ -1,13 +1,17 @@
 use core::{cell::OnceCell, mem::size_of};
 
+use alloc::collections::BTreeMap;
 use buddy_system_allocator::LockedFrameAllocator;
-use erhino_shared::mem::PageNumber;
+use erhino_shared::{mem::PageNumber, sync::spin::SimpleLock};
+use lock_api::Mutex;
 
 use crate::external::_frame_start;
 
 use super::page::{PAGE_BITS, PAGE_SIZE};
 
 static mut FRAME_ALLOCATOR: OnceCell<LockedFrameAllocator<32>> = OnceCell::new();
+// 共享的页帧除了最初的持有者之外还有几个 FrameTracker，最后一个放手时才真正回收
+static SHARES: Mutex<SimpleLock, BTreeMap<PageNumber, usize>> = Mutex::new(BTreeMap::new());
 
 pub struct FrameTracker {
     number: PageNumber,
@@ -29,11 +33,30 @@ impl FrameTracker {
     pub fn start(&self) -> PageNumber {
         self.number
     }
+
+    // 同一组页帧多一个持有者，写时复制的页在父子进程间这样共享
+    pub fn share(&self) -> Self {
+        *SHARES.lock().entry(self.number).or_insert(0) += 1;
+        Self::new(self.number, self.count)
+    }
+
+    pub fn is_shared(&self) -> bool {
+        SHARES.lock().contains_key(&self.number)
+    }
 }
 
 impl Drop for FrameTracker {
     fn drop(&mut self) {
-        dealloc(self.number, self.count)
+        let mut shares = SHARES.lock();
+        if let Some(count) = shares.get_mut(&self.number) {
+            *count -= 1;
+            if *count == 0 {
+                shares.remove(&self.number);
+            }
+        } else {
+            drop(shares);
+            dealloc(self.number, self.count)
+        }
     }
 }
 
@@ -1,4 +1,4 @@
-// Sv39 only
+// Sv39/Sv48/Sv57 的页表项格式相同，只有级数不同，用哪个在启动时选定
 
 use core::{cell::UnsafeCell, fmt::Debug, mem::size_of, ops::Not};
 
@@ -7,12 +7,25 @@ use erhino_shared::mem::{Address, PageNumber};
 use flagset::{flags, FlagSet};
 use hashbrown::HashMap;
 
-use super::frame::FrameTracker;
+use crate::board::device::cpu::MmuType;
+
+use super::frame::{self, FrameTracker};
 
 pub const PAGE_SIZE: usize = 4096;
 pub const PAGE_BITS: usize = 12;
 
-pub type PageEntryImpl = PageTableEntry39;
+pub type PageEntryImpl = PageTableEntry64;
+
+static mut PAGING_MODE: MmuType = MmuType::Sv39;
+
+// 只能在建出第一张页表之前调用，之后级数就不能变了
+pub fn select_paging_mode(mode: MmuType) {
+    unsafe { PAGING_MODE = mode }
+}
+
+pub fn paging_mode() -> MmuType {
+    unsafe { PAGING_MODE }
+}
 
 flags! {
     #[repr(u64)]
@@ -101,8 +114,10 @@ impl<E: PageTableEntry + 'static> PageTable<E> {
         let entry = self.entry_mut(index);
         if entry.is_valid() {
             let number = entry.physical_page_number();
+            // 清掉之后就分不出是叶子还是表了
+            let leaf = entry.is_leaf();
             entry.clear();
-            if entry.is_leaf() {
+            if leaf {
                 self.managed.remove(&index);
             } else {
                 self.branches.remove(&index);
@@ -218,12 +233,17 @@ impl<E: PageTableEntry + 'static> PageTable<E> {
         let entry = self.entry_mut(index);
         if entry.is_valid() {
             if entry.is_leaf() {
-                entry.set_flags(
-                    flags.into()
-                        & (PageEntryFlag::Readable
-                            | PageEntryFlag::Writeable
-                            | PageEntryFlag::Executable),
-                );
+                let mut extended = flags.into()
+                    & (PageEntryFlag::Readable
+                        | PageEntryFlag::Writeable
+                        | PageEntryFlag::Executable);
+                // 共享着的页不能直接放开写，等写的时候复制
+                if entry.has_flags(PageEntryFlag::Cow)
+                    && extended.contains(PageEntryFlag::Writeable)
+                {
+                    extended = (extended - PageEntryFlag::Writeable) | PageEntryFlag::CowWriteable;
+                }
+                entry.set_flags(extended);
                 Ok(false)
             } else {
                 Err(PageEntryWriteError::BranchExists)
@@ -238,6 +258,59 @@ impl<E: PageTableEntry + 'static> PageTable<E> {
         }
     }
 
+    // 把叶子标成写时复制交给另一个页表共享，可写的页去掉写权限，记在 CowWriteable 上
+    // 不归页表管的页帧（跳板、设备寄存器）原样给出，没有 FrameTracker
+    pub fn share_leaf(
+        &mut self,
+        index: usize,
+    ) -> Option<(PageNumber, FlagSet<PageEntryFlag>, Option<FrameTracker>)> {
+        let entry = &mut self.entries[index];
+        if !entry.is_valid() || !entry.is_leaf() {
+            return None;
+        }
+        let tracker = if let Some(owned) = self.managed.get(&index) {
+            if entry.has_flags(PageEntryFlag::Writeable) {
+                entry.clear_flags(PageEntryFlag::Writeable);
+                entry.set_flags(PageEntryFlag::CowWriteable);
+            }
+            entry.set_flags(PageEntryFlag::Cow);
+            Some(owned.share())
+        } else {
+            None
+        };
+        Some((entry.physical_page_number(), entry.flags(), tracker))
+    }
+
+    // 写时复制：还有别人在用就复制一份换上，只剩自己就直接恢复写权限。不是共享的页返回 false
+    pub fn unshare_leaf(&mut self, index: usize) -> Result<bool, PageEntryWriteError> {
+        let entry = &mut self.entries[index];
+        if !entry.is_valid() || !entry.is_leaf() || !entry.has_flags(PageEntryFlag::Cow) {
+            return Ok(false);
+        }
+        if let Some(owned) = self.managed.get(&index)
+            && owned.is_shared()
+        {
+            let copy = frame::borrow(owned.len()).ok_or(PageEntryWriteError::TrackerUnavailable)?;
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    (owned.start() << PAGE_BITS) as *const u8,
+                    (copy.start() << PAGE_BITS) as *mut u8,
+                    owned.len() * PAGE_SIZE,
+                );
+            }
+            let flags = entry.flags();
+            entry.set(copy.start(), flags);
+            // 换下来的那份少一个持有者
+            self.managed.insert(index, copy);
+        }
+        let writeable = entry.has_flags(PageEntryFlag::CowWriteable);
+        entry.clear_flags(PageEntryFlag::Cow | PageEntryFlag::CowWriteable);
+        if writeable {
+            entry.set_flags(PageEntryFlag::Writeable);
+        }
+        Ok(true)
+    }
+
     pub fn get_table(&self, index: usize) -> Option<&PageTable<E>> {
         let entry = self.entry(index);
         if entry.is_valid() && !entry.is_leaf() {
@@ -299,8 +372,8 @@ impl<E: PageTableEntry + 'static> PageTable<E> {
 
 pub trait PageTableEntry: Sized {
     const LENGTH: usize;
-    const DEPTH: usize;
     const SIZE: usize;
+    fn depth() -> usize;
     fn space_size() -> usize;
     fn top_address() -> Address;
     fn is_leaf(&self) -> bool;
@@ -330,12 +403,23 @@ impl<
 {
     const LENGTH: usize = LENGTH;
 
-    const DEPTH: usize = DEPTH;
-
     const SIZE: usize = SIZE;
 
+    // DEPTH 为 0 的按启动时选定的模式走
+    fn depth() -> usize {
+        if DEPTH != 0 {
+            DEPTH
+        } else {
+            match paging_mode() {
+                MmuType::Sv48 => 4,
+                MmuType::Sv57 => 5,
+                _ => 3,
+            }
+        }
+    }
+
     fn space_size() -> usize {
-        1usize << (DEPTH * SIZE + PAGE_BITS - 1)
+        1usize << (Self::depth() * SIZE + PAGE_BITS - 1)
     }
     // 以 Sv39 为例，其虚拟地址空间大小为 2^64
     // 但是 有效位为 (0){25}0(x){38} 或 (1){25}1(x){38}
@@ -401,6 +485,7 @@ pub type PageTableEntry39 = PageTableEntryPrimitive<u64, 56, 3, 9>;
 pub type PageTableEntry48 = PageTableEntryPrimitive<u64, 56, 4, 9>;
 #[allow(unused)]
 pub type PageTableEntry57 = PageTableEntryPrimitive<u64, 56, 5, 9>;
+pub type PageTableEntry64 = PageTableEntryPrimitive<u64, 56, 0, 9>;
 
 impl<'a, E: PageTableEntry + 'static> IntoIterator for &'a PageTable<E> {
     type Item = (PageNumber, PageNumber, usize, FlagSet<PageEntryFlag>);
@@ -410,7 +495,7 @@ impl<'a, E: PageTableEntry + 'static> IntoIterator for &'a PageTable<E> {
     fn into_iter(self) -> Self::IntoIter {
         Self::IntoIter {
             root: self,
-            level: E::DEPTH - 1,
+            level: E::depth() - 1,
             inner: None,
             base: 0,
             current: 0,
@@ -1,11 +1,18 @@
-use core::fmt::Display;
+use core::{
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use erhino_shared::mem::{Address, MemoryRegionAttribute, PageNumber};
 use flagset::FlagSet;
 
-use crate::debug;
+use crate::{
+    debug,
+    hart::{self, HartId},
+};
 
 use super::{
+    asid,
     frame::{self, FrameTracker},
     page::{
         PageEntryFlag, PageEntryType, PageEntryWriteError, PageTable, PageTableEntry, PAGE_BITS,
@@ -26,16 +33,20 @@ pub enum AddressSpace {
 }
 
 pub struct MemoryUnit<E: PageTableEntry + Sized + 'static> {
-    identity: u16,
+    // 见 asid，内核的单元一直是 0
+    identity: AtomicUsize,
+    // 运行过这个单元的核心，改了页表之后要让它们刷掉 TLB
+    harts: AtomicUsize,
     root: PageTable<E>,
     where_the_frame_tracker_of_root_for_recycling_put: FrameTracker,
 }
 
 impl<E: PageTableEntry + Sized + 'static> MemoryUnit<E> {
-    pub fn new(id: u16) -> Result<Self, MemoryUnitError> {
+    pub fn new() -> Result<Self, MemoryUnitError> {
         if let Some(frame) = frame::borrow(1) {
             Ok(Self {
-                identity: id,
+                identity: AtomicUsize::new(0),
+                harts: AtomicUsize::new(0),
                 root: PageTable::<E>::from(frame.start()),
                 where_the_frame_tracker_of_root_for_recycling_put: frame,
             })
@@ -45,21 +56,37 @@ impl<E: PageTableEntry + Sized + 'static> MemoryUnit<E> {
     }
 
     pub fn satp(&self) -> usize {
-        let mode = PAGE_BITS + E::DEPTH * E::SIZE;
+        let mode = PAGE_BITS + E::depth() * E::SIZE;
         let mode_code = match mode {
             32 => 1,
             39 => 8,
             48 => 9,
-            56 => 10,
+            57 => 10,
             _ => 0,
         };
         (mode_code << 60)
-            | ((self.identity as usize) << 44)
+            | asid::satp_field_of(self.identity.load(Ordering::Acquire))
             | self
                 .where_the_frame_tracker_of_root_for_recycling_put
                 .start()
     }
 
+    // 要在这个核心上运行之前调用，ASID 过期了会重新分配，返回的 satp 带着当前的 ASID
+    pub fn activate(&self, hart: HartId) -> usize {
+        let identity = asid::assign(&self.identity);
+        self.harts.fetch_or(1 << hart, Ordering::AcqRel);
+        asid::synchronize(hart, identity);
+        self.satp()
+    }
+
+    // 别的核心改了页表、远程刷新还没送到时本核心可能按旧的 TLB 项出错，只刷掉本核心的这一页
+    pub fn refresh(&self, vpn: PageNumber) {
+        let identity = self.identity.load(Ordering::Acquire);
+        if identity != 0 {
+            asid::flush_local(Some(asid::asid_of(identity)), vpn, 1, false);
+        }
+    }
+
     pub fn is_address_in(addr: Address) -> AddressSpace {
         let top = E::top_address();
         let size = E::space_size();
@@ -78,7 +105,9 @@ impl<E: PageTableEntry + Sized + 'static> MemoryUnit<E> {
         count: usize,
         flags: F,
     ) -> Result<usize, MemoryUnitError> {
-        Self::map_internal(&mut self.root, vpn, None, count, flags, E::DEPTH - 1)
+        let result = Self::map_internal(&mut self.root, vpn, None, count, flags, E::depth() - 1);
+        self.flush(vpn, count);
+        result
     }
 
     pub fn map<F: Into<FlagSet<PageEntryFlag>> + Copy>(
@@ -88,11 +117,35 @@ impl<E: PageTableEntry + Sized + 'static> MemoryUnit<E> {
         count: usize,
         flags: F,
     ) -> Result<usize, MemoryUnitError> {
-        Self::map_internal(&mut self.root, vpn, Some(ppn), count, flags, E::DEPTH - 1)
+        let result =
+            Self::map_internal(&mut self.root, vpn, Some(ppn), count, flags, E::depth() - 1);
+        self.flush(vpn, count);
+        result
     }
 
     pub fn free(&mut self, vpn: PageNumber, count: usize) -> Result<usize, MemoryUnitError> {
-        Self::free_internal(&mut self.root, vpn, count, E::DEPTH - 1)
+        let result = Self::free_internal(&mut self.root, vpn, count, E::depth() - 1);
+        self.flush(vpn, count);
+        result
+    }
+
+    // 只复制用户空间那一半，页帧两边共享，可写的页两边都变成写时复制。返回新的单元和新建页表用掉的页数
+    // 父进程的可写页都改成了只读，它在别的核心上跑着的线程也得刷掉 TLB，不然还能写进共享的页
+    pub fn fork(&mut self) -> Result<(Self, usize), MemoryUnitError> {
+        let mut unit = Self::new()?;
+        let half = PageTable::<E>::entry_count() / 2;
+        let result = Self::fork_internal(&mut self.root, &mut unit.root, half);
+        self.flush(0, usize::MAX);
+        Ok((unit, result?))
+    }
+
+    // 写之前把写时复制的页变成自己的，不是共享的页返回 false
+    pub fn unshare(&mut self, vpn: PageNumber) -> Result<bool, MemoryUnitError> {
+        let unshared = Self::unshare_internal(&mut self.root, vpn, E::depth() - 1)?;
+        if unshared {
+            self.flush(vpn, 1);
+        }
+        Ok(unshared)
     }
 
     pub fn translate(&self, addr: Address) -> Option<(Address, FlagSet<MemoryRegionAttribute>)> {
@@ -104,6 +157,15 @@ impl<E: PageTableEntry + Sized + 'static> MemoryUnit<E> {
         }
     }
 
+    fn flush(&self, vpn: PageNumber, count: usize) {
+        let identity = self.identity.load(Ordering::Acquire);
+        let harts = self.harts.load(Ordering::Acquire);
+        // 没有核心运行过就不会有 TLB 项
+        if identity != 0 && harts != 0 {
+            asid::flush(harts, hart::hartid(), identity, vpn, count);
+        }
+    }
+
     fn free_internal(
         container: &mut PageTable<E>,
         vpn: PageNumber,
@@ -445,8 +507,53 @@ impl<E: PageTableEntry + Sized + 'static> MemoryUnit<E> {
         }
     }
 
+    fn fork_internal(
+        container: &mut PageTable<E>,
+        target: &mut PageTable<E>,
+        count: usize,
+    ) -> Result<usize, MemoryUnitError> {
+        let mut created = 0usize;
+        for index in 0..count {
+            if let Some(table) = container.get_table_mut(index) {
+                let branch = match target.ensure_table_created(index, || {
+                    created += 1;
+                    frame::borrow(1)
+                }) {
+                    Ok(branch) => branch,
+                    Err(_) => return Err(MemoryUnitError::RanOutOfFrames),
+                };
+                Self::fork_internal(table, branch, PageTable::<E>::entry_count())
+                    .map(|c| created += c)?;
+            } else if let Some((number, flags, tracker)) = container.share_leaf(index) {
+                if let Some(shared) = tracker {
+                    Self::into_result(
+                        target
+                            .create_managed_leaf(index, shared, flags)
+                            .map(|_| true),
+                    )?;
+                } else {
+                    Self::into_result(target.create_leaf(index, number, flags).map(|_| true))?;
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    fn unshare_internal(
+        container: &mut PageTable<E>,
+        vpn: PageNumber,
+        level: usize,
+    ) -> Result<bool, MemoryUnitError> {
+        let index = Self::index_of_vpn(vpn, level);
+        if let Some(table) = container.get_table_mut(index) {
+            Self::unshare_internal(table, vpn, level - 1)
+        } else {
+            Self::into_result(container.unshare_leaf(index))
+        }
+    }
+
     fn locate(&self, vpn: PageNumber) -> Option<(PageNumber, FlagSet<MemoryRegionAttribute>)> {
-        Self::locate_internal(&self.root, vpn, E::DEPTH - 1)
+        Self::locate_internal(&self.root, vpn, E::depth() - 1)
     }
 
     fn locate_internal(
@@ -578,7 +685,7 @@ impl<E: PageTableEntry + Sized + 'static> Display for MemoryUnit<E> {
                 .start()
                 << PAGE_BITS
         )?;
-        let highest = 1 << (E::SIZE * E::DEPTH - 1);
+        let highest = 1 << (E::SIZE * E::depth() - 1);
         let upper_bits = (E::top_address() >> PAGE_BITS) - (highest - 1);
         let vpn_fmt: fn(Address, usize, usize) -> Address = |x, h, u| {
             if x & h != 0 {
@@ -4,6 +4,7 @@ pub struct MemoryUsage {
     pub program: usize,
     pub heap: usize,
     pub stack: usize,
+    pub mapped: usize,
 }
 
 impl MemoryUsage {
@@ -13,6 +14,7 @@ impl MemoryUsage {
             program: 0,
             heap: 0,
             stack: 0,
+            mapped: 0,
         }
     }
 }
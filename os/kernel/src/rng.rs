@@ -1,3 +1,4 @@
+pub mod chacha;
 pub mod lcg;
 
 pub trait RandomGenerator{
@@ -0,0 +1,79 @@
+use super::RandomGenerator;
+
+// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+const DOUBLE_ROUNDS: usize = 10;
+
+// ChaCha20 快速擦除密钥：每生成一块就用前半块替换掉密钥，只输出后半块，拿到输出也推不出之前和之后的状态
+pub struct ChaChaGenerator {
+    key: [u32; 8],
+    nonce: [u32; 2],
+    buffer: [u32; 8],
+    cursor: usize,
+}
+
+impl ChaChaGenerator {
+    // 种子相同的核心也会得到不同的流
+    pub fn new(seed: usize, stream: usize) -> Self {
+        let mut key = [0u32; 8];
+        key[0] = seed as u32;
+        key[1] = (seed >> 32) as u32;
+        Self {
+            key,
+            nonce: [stream as u32, (stream >> 32) as u32],
+            buffer: [0; 8],
+            cursor: 8,
+        }
+    }
+
+    fn refill(&mut self) {
+        let mut state = [0u32; 16];
+        state[..4].copy_from_slice(&CONSTANTS);
+        state[4..12].copy_from_slice(&self.key);
+        state[14] = self.nonce[0];
+        state[15] = self.nonce[1];
+        let mut working = state;
+        for _ in 0..DOUBLE_ROUNDS {
+            quarter_round(&mut working, 0, 4, 8, 12);
+            quarter_round(&mut working, 1, 5, 9, 13);
+            quarter_round(&mut working, 2, 6, 10, 14);
+            quarter_round(&mut working, 3, 7, 11, 15);
+            quarter_round(&mut working, 0, 5, 10, 15);
+            quarter_round(&mut working, 1, 6, 11, 12);
+            quarter_round(&mut working, 2, 7, 8, 13);
+            quarter_round(&mut working, 3, 4, 9, 14);
+        }
+        for i in 0..16 {
+            working[i] = working[i].wrapping_add(state[i]);
+        }
+        self.key.copy_from_slice(&working[..8]);
+        self.buffer.copy_from_slice(&working[8..]);
+        self.cursor = 0;
+    }
+}
+
+impl RandomGenerator for ChaChaGenerator {
+    fn next(&mut self) -> usize {
+        if self.cursor + 2 > self.buffer.len() {
+            self.refill();
+        }
+        let low = self.buffer[self.cursor] as usize;
+        let high = self.buffer[self.cursor + 1] as usize;
+        // 用过的输出也不留在内存里
+        self.buffer[self.cursor] = 0;
+        self.buffer[self.cursor + 1] = 0;
+        self.cursor += 2;
+        (high << 32) | low
+    }
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
@@ -13,7 +13,7 @@ use crate::{
         frame::{self, alloc},
         page::PAGE_SIZE,
     },
-    println, sbi,
+    println, sbi, timer,
 };
 
 const HEAP_ORDER: usize = 32;
@@ -48,12 +48,15 @@ fn early_init(dtb_addr: usize) {
     let tree = DeviceTree::from_address(dtb_addr).expect("device tree not available");
     board::init(tree);
     let board = board::this_board();
+    // 全局时钟和各核心的调度计时器用同一个频率
+    let frequency = board.mtime_frequency();
+    timer::init(frequency);
     if let Some((addr, _)) = board.initfs() {
         frame::init(addr);
     }else{
         panic!("no initfs info");
     }
-    hart::init();
+    hart::init(frequency);
 }
 
 fn kernel_init() {
@@ -72,6 +72,34 @@ fn raw_call(
     (error, value)
 }
 
+// 和 raw_call 一样，只有 RFENCE 这样的少数调用需要五个参数
+#[inline]
+fn raw_call_extended(
+    eid: SbiExtension,
+    fid: usize,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+) -> (isize, isize) {
+    let mut error: isize;
+    let mut value: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") arg0 => error,
+            inlateout("a1") arg1 => value,
+            in("a2") arg2,
+            in("a3") arg3,
+            in("a4") arg4,
+            in("a6") fid,
+            in("a7") eid as usize
+        );
+    }
+    (error, value)
+}
+
 #[inline]
 fn legacy_call(eid: SbiExtension, arg0: usize, arg1: usize, arg2: usize) -> usize {
     let mut ret: usize;
@@ -88,6 +116,25 @@ fn legacy_call(eid: SbiExtension, arg0: usize, arg1: usize, arg2: usize) -> usiz
 #[inline]
 fn sbi_call(eid: SbiExtension, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> SbiResult {
     let (error, value) = raw_call(eid, fid, arg0, arg1, arg2);
+    into_result(error, value)
+}
+
+#[inline]
+fn sbi_call_extended(
+    eid: SbiExtension,
+    fid: usize,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+) -> SbiResult {
+    let (error, value) = raw_call_extended(eid, fid, arg0, arg1, arg2, arg3, arg4);
+    into_result(error, value)
+}
+
+#[inline]
+fn into_result(error: isize, value: isize) -> SbiResult {
     if error == 0 {
         Ok(value)
     } else {
@@ -163,6 +210,58 @@ pub fn send_ipi(hart_mask: usize, hart_mask_base: isize) -> SbiResult {
     )
 }
 
+// RFENCE extension #0x52464E43
+
+// size 为 usize::MAX 时刷掉全部项
+pub fn remote_sfence_vma(
+    hart_mask: usize,
+    hart_mask_base: isize,
+    start_addr: usize,
+    size: usize,
+) -> SbiResult {
+    if is_remote_fence_supported() {
+        sbi_call_extended(
+            SbiExtension::RemoteFence,
+            1,
+            hart_mask,
+            hart_mask_base as usize,
+            start_addr,
+            size,
+            0,
+        )
+    } else {
+        Ok(legacy_call(
+            SbiExtension::LegacyRemoteSFenceVma,
+            &hart_mask as *const usize as usize,
+            start_addr,
+            size,
+        ) as isize)
+    }
+}
+
+pub fn remote_sfence_vma_asid(
+    hart_mask: usize,
+    hart_mask_base: isize,
+    start_addr: usize,
+    size: usize,
+    asid: usize,
+) -> SbiResult {
+    if is_remote_fence_supported() {
+        sbi_call_extended(
+            SbiExtension::RemoteFence,
+            2,
+            hart_mask,
+            hart_mask_base as usize,
+            start_addr,
+            size,
+            asid,
+        )
+    } else {
+        // 旧接口带 ASID 的版本要四个参数，退而求其次不分 ASID 地刷
+        remote_sfence_vma(hart_mask, hart_mask_base, start_addr, size)
+    }
+}
+
 // Hart State Management extension #0x48534D
 
 pub fn hart_start(hartid: HartId, start_addr: usize, opaque: usize) -> SbiResult {
@@ -219,6 +318,7 @@ pub fn debug_console_write_byte(byte: u8) -> SbiResult {
 
 static mut TIME_SUPPORTED: bool = false;
 static mut DEBUG_CONSOLE_SUPPORTED: bool = false;
+static mut REMOTE_FENCE_SUPPORTED: bool = false;
 
 pub fn is_debug_console_supported() -> bool {
     unsafe { DEBUG_CONSOLE_SUPPORTED }
@@ -227,6 +327,10 @@ pub fn is_debug_console_supported() -> bool {
 pub fn is_time_supported() -> bool {
     unsafe { TIME_SUPPORTED }
 }
+
+pub fn is_remote_fence_supported() -> bool {
+    unsafe { REMOTE_FENCE_SUPPORTED }
+}
 pub fn init() {
     if let Ok(res) = sbi_probe_extension(SbiExtension::DebugConsole) {
         unsafe {
@@ -238,4 +342,9 @@ pub fn init() {
             TIME_SUPPORTED = res != 0;
         }
     }
+    if let Ok(res) = sbi_probe_extension(SbiExtension::RemoteFence) {
+        unsafe {
+            REMOTE_FENCE_SUPPORTED = res != 0;
+        }
+    }
 }
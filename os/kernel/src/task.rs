@@ -1,4 +1,5 @@
 pub mod ipc;
 pub mod proc;
+pub mod request;
 pub mod sched;
-pub mod thread;
+pub mod thread;
\ No newline at end of file
@@ -1,10 +1,17 @@
-use alloc::vec::Vec;
-use erhino_shared::{message::MessageDigest, proc::Pid, time::Timestamp};
+use alloc::{collections::VecDeque, vec::Vec};
+use crate::timer;
+use erhino_shared::{
+    call::SystemCallError,
+    message::MessageDigest,
+    proc::{Pid, Rid},
+    time::Timestamp,
+};
 
 pub struct Message {
     sender: Pid,
     kind: usize,
     time: Timestamp,
+    reply: Rid,
     content: Vec<u8>,
 }
 
@@ -13,13 +20,35 @@ impl Message {
         Self {
             sender,
             kind,
-            time: 0,
+            time: timer::monotonic(),
+            reply: 0,
+            content,
+        }
+    }
+
+    // 由 Call 发出，发送方阻塞在 reply 这个请求上等待回复
+    pub fn with_reply(sender: Pid, kind: usize, content: Vec<u8>, reply: Rid) -> Self {
+        Self {
+            sender,
+            kind,
+            time: timer::monotonic(),
+            reply,
             content,
         }
     }
 
     pub fn digest(&self) -> MessageDigest {
-        MessageDigest::new(self.sender, self.kind, self.time, self.content.len())
+        MessageDigest::new(
+            self.sender,
+            self.kind,
+            self.time,
+            self.content.len(),
+            self.reply,
+        )
+    }
+
+    pub fn reply(&self) -> Rid {
+        self.reply
     }
 
     pub fn len(&self) -> usize {
@@ -29,31 +58,91 @@ impl Message {
     pub fn content(&self) -> &[u8] {
         &self.content
     }
+
+    pub fn sender(&self) -> Pid {
+        self.sender
+    }
+}
+
+pub const MAILBOX_DEFAULT_CAPACITY: usize = 16;
+pub const MAILBOX_DEFAULT_QUOTA: usize = 4;
+pub const MAILBOX_CAPACITY_LIMIT: usize = 256;
+
+#[derive(Debug)]
+pub enum MailboxError {
+    Full,
+    QuotaExceeded,
 }
 
+impl Into<SystemCallError> for MailboxError {
+    fn into(self) -> SystemCallError {
+        match self {
+            Self::Full => SystemCallError::ObjectNotAvailable,
+            Self::QuotaExceeded => SystemCallError::ReachLimit,
+        }
+    }
+}
+
+// 先进先出，容量满了或者某个发送方占的格子超过配额都会拒收，避免一个话多的客户端把服务的邮箱塞满
 pub struct Mailbox {
-    inbox: Option<Message>,
+    inbox: VecDeque<Message>,
+    capacity: usize,
+    quota: usize,
 }
 
 impl Mailbox {
     pub fn new() -> Self {
-        Self { inbox: None }
+        Self::with_capacity(MAILBOX_DEFAULT_CAPACITY, MAILBOX_DEFAULT_QUOTA)
+    }
+
+    pub fn with_capacity(capacity: usize, quota: usize) -> Self {
+        Self {
+            inbox: VecDeque::new(),
+            capacity,
+            quota,
+        }
     }
 
     pub fn available(&self) -> bool {
-        self.inbox.is_none()
+        self.inbox.len() < self.capacity
     }
 
-    pub fn put(&mut self, msg: Message) -> bool {
-        if self.inbox.is_some() {
-            false
+    pub fn len(&self) -> usize {
+        self.inbox.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // 缩容不会丢弃已经在队列中的消息，只是在回落到容量以下之前不再接收
+    pub fn configure(&mut self, capacity: usize, quota: usize) {
+        self.capacity = capacity;
+        self.quota = quota;
+    }
+
+    pub fn put(&mut self, msg: Message) -> Result<(), MailboxError> {
+        if !self.available() {
+            Err(MailboxError::Full)
+        } else if self
+            .inbox
+            .iter()
+            .filter(|m| m.sender == msg.sender)
+            .count()
+            >= self.quota
+        {
+            Err(MailboxError::QuotaExceeded)
         } else {
-            self.inbox = Some(msg);
-            true
+            self.inbox.push_back(msg);
+            Ok(())
         }
     }
 
     pub fn take(&mut self) -> Option<Message> {
-        self.inbox.take()
+        self.inbox.pop_front()
+    }
+
+    pub fn clear(&mut self) -> Vec<Message> {
+        self.inbox.drain(..).collect()
     }
 }
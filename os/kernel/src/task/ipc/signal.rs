@@ -1,18 +1,34 @@
 use core::mem::size_of;
 
-use erhino_shared::{mem::Address, proc::SignalMap};
+use alloc::collections::VecDeque;
+use erhino_shared::{
+    mem::Address,
+    proc::{SignalMap, SystemSignal},
+    time::Timestamp,
+};
 
 use crate::trap::TrapFrame;
 
+const SIGNAL_COUNT: usize = size_of::<SignalMap>() * 8;
+
+// 随信号一起交给处理函数的两个参数
+pub type SignalArgument = (usize, usize);
+// 不能被捕获也不能被屏蔽
+const UNCATCHABLE: SignalMap = SystemSignal::Kill as SignalMap;
+
 pub struct SignalControlBlock {
     x: [u64; 32],
     f: [u64; 32],
     pc: u64,
-    mask: SignalMap,
+    // 按信号位序号索引
+    handlers: [Option<Address>; SIGNAL_COUNT],
+    blocked: SignalMap,
     pending: SignalMap,
+    // 携带参数的信号，同一信号可以排队多个参数，全部取完才清除 pending 位
+    arguments: VecDeque<(SignalMap, SignalArgument)>,
+    alarm: Option<Timestamp>,
     handling: bool,
     complete: bool,
-    handler: Option<Address>,
 }
 
 impl SignalControlBlock {
@@ -21,46 +37,97 @@ impl SignalControlBlock {
             x: [0; 32],
             f: [0; 32],
             pc: 0,
-            mask: 0,
+            handlers: [None; SIGNAL_COUNT],
+            blocked: 0,
             pending: 0,
+            arguments: VecDeque::new(),
+            alarm: None,
             handling: false,
             complete: false,
-            handler: None,
         }
     }
 
+    // 给 fork 出来的子进程用：保留处理函数、屏蔽掩码和正在处理的信号，不带待处理的
+    pub fn inherit(&self) -> Self {
+        Self {
+            x: self.x,
+            f: self.f,
+            pc: self.pc,
+            handlers: self.handlers,
+            blocked: self.blocked,
+            pending: 0,
+            arguments: VecDeque::new(),
+            alarm: None,
+            handling: self.handling,
+            complete: false,
+        }
+    }
+
+    // 有处理函数，早晚会送达
     pub fn is_accepted(&self, signal: SignalMap) -> bool {
-        signal & self.mask != 0
+        signal != 0 && self.handler_of(signal).is_some()
     }
 
-    pub fn has_pending(&self) -> bool {
-        self.pending > 0
+    pub fn is_blocked(&self, signal: SignalMap) -> bool {
+        self.blocked & signal != 0
+    }
+
+    pub fn is_catchable(signal: SignalMap) -> bool {
+        signal & UNCATCHABLE == 0
+    }
+
+    fn handler_of(&self, signal: SignalMap) -> Option<Address> {
+        self.handlers[signal.trailing_zeros() as usize]
+    }
+
+    // 有没被屏蔽且有处理函数的待处理信号
+    pub fn has_deliverable(&self) -> bool {
+        self.deliverable() != 0
+    }
+
+    fn deliverable(&self) -> SignalMap {
+        let mut handled: SignalMap = 0;
+        for (i, handler) in self.handlers.iter().enumerate() {
+            if handler.is_some() {
+                handled |= 1 << i;
+            }
+        }
+        self.pending & !self.blocked & handled
     }
 
     pub fn is_handling(&self) -> bool {
         self.handling
     }
 
+    // 没有处理函数或者被屏蔽的信号也会留着，等到可以投递的时候再投递
     pub fn enqueue(&mut self, signal: SignalMap) {
         self.pending |= signal;
     }
 
-    pub fn dequeue(&mut self) -> SignalMap {
-        let mut pending = self.pending;
-        let mut signal = 0u64;
-        for i in 0..(size_of::<SignalMap>() * 8) {
-            if pending & 0b1 == 1 {
-                signal = 1 << i;
-                break;
-            } else {
-                pending >>= 1;
-            }
+    // 带着参数入队，信号和参数都相同的合并成一个
+    pub fn enqueue_with(&mut self, signal: SignalMap, argument: SignalArgument) {
+        if !self.arguments.contains(&(signal, argument)) {
+            self.arguments.push_back((signal, argument));
+        }
+        self.pending |= signal;
+    }
+
+    // 取出编号最小的可送达信号及其参数（没带参数的为零）和处理函数
+    pub fn dequeue(&mut self) -> Option<(SignalMap, SignalArgument, Address)> {
+        let deliverable = self.deliverable();
+        if deliverable == 0 {
+            return None;
+        }
+        let signal: SignalMap = 1 << deliverable.trailing_zeros();
+        let mut argument: SignalArgument = (0, 0);
+        if let Some(index) = self.arguments.iter().position(|(s, _)| *s == signal) {
+            argument = self.arguments.remove(index).unwrap().1;
         }
-        self.pending &= !signal;
-        if signal > 0 {
-            self.handling = true;
+        if !self.arguments.iter().any(|(s, _)| *s == signal) {
+            self.pending &= !signal;
         }
-        signal
+        self.handling = true;
+        self.handler_of(signal).map(|handler| (signal, argument, handler))
     }
 
     pub fn complete(&mut self) {
@@ -76,17 +143,34 @@ impl SignalControlBlock {
         self.complete = false;
     }
 
-    pub fn set_handler(&mut self, mask: SignalMap, handler: Address) {
-        self.handler = Some(handler);
-        self.mask = mask;
+    // 设置或清除（None）掩码里每个信号的处理函数，其中有不可捕获的就返回 false
+    pub fn set_handler(&mut self, mask: SignalMap, handler: Option<Address>) -> bool {
+        if !Self::is_catchable(mask) {
+            return false;
+        }
+        for i in 0..SIGNAL_COUNT {
+            if mask & (1 << i) != 0 {
+                self.handlers[i] = handler;
+            }
+        }
+        true
+    }
+
+    pub fn blocked(&self) -> SignalMap {
+        self.blocked
+    }
+
+    // 替换屏蔽掩码，不可捕获的信号永远不会被屏蔽
+    pub fn set_blocked(&mut self, mask: SignalMap) {
+        self.blocked = mask & !UNCATCHABLE;
     }
 
-    pub fn has_handler(&self) -> bool {
-        self.handler.is_some()
+    pub fn alarm(&self) -> Option<Timestamp> {
+        self.alarm
     }
 
-    pub fn handler(&self) -> Option<Address> {
-        self.handler
+    pub fn set_alarm(&mut self, deadline: Option<Timestamp>) {
+        self.alarm = deadline;
     }
 
     pub fn backup(&mut self, trapframe: &TrapFrame) {
@@ -1,12 +1,24 @@
+use alloc::vec::Vec;
 use erhino_shared::{mem::PageNumber, proc::Pid};
 
 use crate::mm::frame::FrameTracker;
 
+// 单个通道最多这么多页
+pub const TUNNEL_PAGE_LIMIT: usize = 256;
+// 一个进程作为持有者的所有通道加起来最多这么多页
+pub const TUNNEL_PAGE_LIMIT_PER_PROCESS: usize = 1024;
+
 pub struct Tunnel {
     key: usize,
     pub owner: Pid,
     pub first: Option<(Pid, PageNumber)>,
     pub second: Option<(Pid, PageNumber)>,
+    // 除了持有者以外唯一允许连接的进程，连上后作废
+    granted: Option<Pid>,
+    // 收到了对端中断但当时没有线程在等的端点
+    bells: Vec<Pid>,
+    // 有一端离开而另一端还在
+    broken: bool,
     frame: FrameTracker,
 }
 
@@ -17,6 +29,9 @@ impl Tunnel {
             owner: owner,
             first: None,
             second: None,
+            granted: None,
+            bells: Vec::new(),
+            broken: false,
             frame,
         }
     }
@@ -29,24 +44,79 @@ impl Tunnel {
         self.frame.start()
     }
 
-    pub fn link(&mut self, pid: Pid, number: PageNumber) -> bool {
-        if let Some((first, _)) = self.first {
-            if self.second.is_none() {
-                if pid == self.owner || first == self.owner {
-                    self.second = Some((pid, number));
-                    return true;
-                }
-            }
+    pub fn page_count(&self) -> usize {
+        self.frame.len()
+    }
+
+    // 一端已经离开，另一端还连着
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    pub fn is_linked(&self, pid: Pid) -> bool {
+        self.first.is_some_and(|(f, _)| f == pid) || self.second.is_some_and(|(s, _)| s == pid)
+    }
+
+    // 通道的另一端，没连上就是 None
+    pub fn peer(&self, pid: Pid) -> Option<Pid> {
+        match (self.first, self.second) {
+            (Some((f, _)), Some((s, _))) if f == pid => Some(s),
+            (Some((f, _)), Some((s, _))) if s == pid => Some(f),
+            _ => None,
+        }
+    }
+
+    // 给端点留一次中断，等它之后来取
+    pub fn ring(&mut self, pid: Pid) {
+        if !self.bells.contains(&pid) {
+            self.bells.push(pid);
+        }
+    }
+
+    // 取走留给端点的中断
+    pub fn answer(&mut self, pid: Pid) -> bool {
+        if let Some(index) = self.bells.iter().position(|b| *b == pid) {
+            self.bells.swap_remove(index);
+            true
         } else {
+            false
+        }
+    }
+
+    // 允许该进程连接，只有持有者或已连上的端点能授权
+    pub fn grant(&mut self, by: Pid, to: Pid) -> bool {
+        if by == self.owner || self.is_linked(by) {
+            self.granted = Some(to);
+            true
+        } else {
+            false
+        }
+    }
+
+    // key 泄露了也没用，只有持有者和被授权的进程能连上
+    pub fn link(&mut self, pid: Pid, number: PageNumber) -> bool {
+        if pid != self.owner && self.granted != Some(pid) {
+            return false;
+        }
+        if self.first.is_none() {
             self.first = Some((pid, number));
             self.owner = pid;
-            return true;
+        } else if self.second.is_none() {
+            self.second = Some((pid, number));
+            self.broken = false;
+        } else {
+            return false;
         }
-        false
+        if self.granted == Some(pid) {
+            self.granted = None;
+        }
+        true
     }
 
     pub fn unlink(&mut self, pid: Pid) -> Option<(bool, PageNumber)> {
+        self.bells.retain(|b| *b != pid);
         if self.second.is_some_and(|(s, _)| s == pid) {
+            self.broken = true;
             return self.second.take().map(|f| (false, f.1));
         } else {
             if self.first.is_some_and(|(f, _)| f == pid) {
@@ -54,6 +124,7 @@ impl Tunnel {
                 self.first = self.second.take();
                 if let Some((second, _)) = self.first {
                     self.owner = second;
+                    self.broken = true;
                     first.map(|f| (false, f.1))
                 } else {
                     first.map(|f| (true, f.1))
@@ -67,12 +138,13 @@ impl Tunnel {
 
 pub struct Endpoint {
     index: usize,
+    count: usize,
     key: usize,
 }
 
 impl Endpoint {
-    pub const fn new(index: usize, key: usize) -> Self {
-        Self { index, key }
+    pub const fn new(index: usize, count: usize, key: usize) -> Self {
+        Self { index, count, key }
     }
 
     pub fn key(&self) -> usize {
@@ -82,4 +154,8 @@ impl Endpoint {
     pub fn index(&self) -> usize {
         self.index
     }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
 }
@@ -1,16 +1,20 @@
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
+use core::mem::size_of;
 use elf_rs::{Elf, ElfFile, ElfMachine, ElfType, ProgramHeaderFlags, ProgramType};
 use erhino_shared::{
     call::SystemCallError,
-    mem::{Address, MemoryRegionAttribute, PageNumber},
-    proc::{ExitCode, ProcessPermission},
+    mem::{Address, MemoryLimit, MemoryOperation, MemoryRegionAttribute, PageNumber},
+    proc::{AuxiliaryKey, ExitCode, Pid, ProcessPermission},
 };
 use flagset::FlagSet;
 
-use crate::mm::{
-    page::{PageEntryFlag, PageEntryImpl, PageTableEntry, PAGE_BITS, PAGE_SIZE},
-    unit::{MemoryUnit, MemoryUnitError},
-    usage::MemoryUsage,
+use crate::{
+    hart::HartId,
+    mm::{
+        page::{PageEntryFlag, PageEntryImpl, PageTableEntry, PAGE_BITS, PAGE_SIZE},
+        unit::{MemoryUnit, MemoryUnitError},
+        usage::MemoryUsage,
+    },
 };
 
 use super::ipc::{
@@ -39,11 +43,48 @@ pub enum ProcessMemoryError {
     InaccessibleRegion,
 }
 
+// TLS 块最大多少，块放在线程栈顶，不能把栈吃光
+const THREAD_LOCAL_LIMIT: usize = 64 * PAGE_SIZE;
+
+// PT_TLS 段给出的模板，每个线程都按它初始化自己的 TLS 块
+#[derive(Clone)]
+pub struct ThreadLocalTemplate {
+    image: Vec<u8>,
+    memory_size: usize,
+    align: usize,
+}
+
+// 程序段里全是零的页，第一次访问时才分配
+#[derive(Clone)]
+struct LazyRegion {
+    start: PageNumber,
+    count: usize,
+    attributes: FlagSet<MemoryRegionAttribute>,
+}
+
+// Map 建立的区域
+#[derive(Clone)]
+struct Mapping {
+    start: PageNumber,
+    count: usize,
+}
+
 #[derive(Debug)]
 pub enum ProcessTunnelError {
     ReachLimit,
 }
 
+impl Into<SystemCallError> for ProcessSpawnError {
+    fn into(self) -> SystemCallError {
+        match self {
+            Self::BrokenBinary => SystemCallError::IllegalArgument,
+            Self::WrongTarget => SystemCallError::NotSupported,
+            Self::InvalidPermissions => SystemCallError::PermissionDenied,
+            Self::MemoryError(err) => err.into(),
+        }
+    }
+}
+
 impl From<MemoryUnitError> for ProcessMemoryError {
     fn from(value: MemoryUnitError) -> Self {
         match value {
@@ -60,6 +101,7 @@ impl Into<SystemCallError> for ProcessMemoryError {
             Self::InaccessibleRegion => SystemCallError::MemoryNotAccessible,
             Self::OutOfMemory => SystemCallError::OutOfMemory,
             Self::MisalignedAddress => SystemCallError::InvalidAddress,
+            Self::ConflictingMapping => SystemCallError::MemoryNotAccessible,
             _ => SystemCallError::Unknown,
         }
     }
@@ -74,12 +116,21 @@ pub enum ProcessHealth {
 pub struct Process {
     memory: MemoryUnit<PageEntryImpl>,
     pub usage: MemoryUsage,
+    pub limit: MemoryLimit,
     entry_point: Address,
     break_point: Address,
     stack_point: Address,
     tunnel_point: Address,
+    map_ceiling: Address,
+    mappings: Vec<Mapping>,
+    lazy: Vec<LazyRegion>,
     permissions: FlagSet<ProcessPermission>,
     tunnels: Vec<Endpoint>,
+    thread_local: Option<ThreadLocalTemplate>,
+    arguments: Vec<String>,
+    variables: Vec<String>,
+    // 主线程栈顶上辅助向量、参数和 TLS 块的最低地址，rinlib 一直引用着它们
+    startup: Option<Address>,
     pub mailbox: Mailbox,
     pub health: ProcessHealth,
     pub signal: SignalControlBlock,
@@ -98,9 +149,18 @@ impl Process {
                 tunnel_point: (PageEntryImpl::space_size() - (PAGE_SIZE * TUNNEL_LIMIT)
                     + (PAGE_SIZE - 1))
                     & !(PAGE_SIZE - 1),
-                memory: MemoryUnit::new(0).unwrap(),
+                // 由调度器按栈区的大小下调
+                map_ceiling: PageEntryImpl::space_size() - (PAGE_SIZE * TUNNEL_LIMIT),
+                mappings: Vec::new(),
+                lazy: Vec::new(),
+                memory: MemoryUnit::new().unwrap(),
                 usage: MemoryUsage::new(),
+                limit: MemoryLimit::UNLIMITED,
                 tunnels: Vec::new(),
+                thread_local: None,
+                arguments: Vec::new(),
+                variables: Vec::new(),
+                startup: None,
                 health: ProcessHealth::Healthy,
                 mailbox: Mailbox::new(),
                 signal: SignalControlBlock::new(),
@@ -115,27 +175,69 @@ impl Process {
             for ph in elf.program_header_iter() {
                 if ph.ph_type() == ProgramType::LOAD {
                     let addr = ph.vaddr() as usize;
-                    if let Some(content) = ph.content() {
-                        let length = ph.memsz() as usize;
-                        let vpn = addr >> PAGE_BITS;
-                        let attr = flags_to_attrs(ph.flags());
+                    let length = ph.memsz() as usize;
+                    // 段必须落在栈下面，不然会和栈、隧道甚至内核空间重叠
+                    let end = if let Some(end) = addr.checked_add(length)
+                        && end <= process.stack_point
+                    {
+                        end
+                    } else {
+                        return Err(ProcessSpawnError::BrokenBinary);
+                    };
+                    let content = ph.content().unwrap_or(&[]);
+                    let vpn = addr >> PAGE_BITS;
+                    let last = (end + PAGE_SIZE - 1) >> PAGE_BITS;
+                    let attr = flags_to_attrs(ph.flags());
+                    // 有文件内容的页现在就填，后面全是零的页（.bss）等第一次访问再分配
+                    let loaded = ((addr + content.len().min(length) + PAGE_SIZE - 1) >> PAGE_BITS)
+                        .min(last);
+                    if loaded > vpn {
                         process
-                            .fill(
-                                vpn,
-                                ((addr + length + PAGE_SIZE - 1) >> PAGE_BITS) - vpn,
-                                attr,
-                                false,
-                            )
+                            .fill(vpn, loaded - vpn, attr, false)
                             .map(|w| page_used += w)
                             .map_err(|e| ProcessSpawnError::MemoryError(e))?;
-                        process
-                            .write(addr as Address, content, length)
-                            .map(|w| byte_used += w)
-                            .map_err(|e| ProcessSpawnError::MemoryError(e))?;
+                        let written = length.min((loaded << PAGE_BITS) - addr);
+                        if written > 0 {
+                            process
+                                .write(addr as Address, content, written)
+                                .map_err(|e| ProcessSpawnError::MemoryError(e))?;
+                        }
                     }
-                    if addr > max_addr {
-                        max_addr = addr;
+                    if last > loaded.max(vpn) {
+                        process.lazy.push(LazyRegion {
+                            start: loaded.max(vpn),
+                            count: last - loaded.max(vpn),
+                            attributes: attr,
+                        });
+                    }
+                    byte_used += length;
+                    if end > max_addr {
+                        max_addr = end;
                     }
+                } else if ph.ph_type() == ProgramType::TLS {
+                    let file_size = ph.filesz() as usize;
+                    let memory_size = ph.memsz() as usize;
+                    let align = (ph.align() as usize).max(1);
+                    if file_size > memory_size
+                        || memory_size > THREAD_LOCAL_LIMIT
+                        || !align.is_power_of_two()
+                        || align > PAGE_SIZE
+                    {
+                        return Err(ProcessSpawnError::BrokenBinary);
+                    }
+                    // .tdata 的内容拷一份留着，.tbss 部分在初始化时补零
+                    let image = match ph.content() {
+                        Some(content) if content.len() >= file_size => {
+                            content[..file_size].to_vec()
+                        }
+                        _ if file_size == 0 => Vec::new(),
+                        _ => return Err(ProcessSpawnError::BrokenBinary),
+                    };
+                    process.thread_local = Some(ThreadLocalTemplate {
+                        image,
+                        memory_size,
+                        align,
+                    });
                 }
             }
             let brk = ((max_addr as usize + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)).next_power_of_two();
@@ -148,6 +250,65 @@ impl Process {
         }
     }
 
+    // 复制出一个和自己共享全部用户页的子进程，谁先写谁复制。隧道不会带过去，邮箱是空的
+    pub fn fork(&mut self) -> Result<Self, ProcessMemoryError> {
+        let (memory, created) = self.memory.fork()?;
+        let mut usage = self.usage.clone();
+        usage.page += created;
+        let mut child = Self {
+            memory,
+            usage,
+            limit: self.limit,
+            entry_point: self.entry_point,
+            break_point: self.break_point,
+            stack_point: self.stack_point,
+            tunnel_point: self.tunnel_point,
+            map_ceiling: self.map_ceiling,
+            mappings: self.mappings.clone(),
+            lazy: self.lazy.clone(),
+            permissions: self.permissions,
+            tunnels: Vec::new(),
+            thread_local: self.thread_local.clone(),
+            arguments: self.arguments.clone(),
+            variables: self.variables.clone(),
+            startup: self.startup,
+            mailbox: Mailbox::new(),
+            health: ProcessHealth::Healthy,
+            signal: self.signal.inherit(),
+        };
+        // 隧道页归隧道所有，子进程没有连上，不能留着映射
+        child.free(self.tunnel_point >> PAGE_BITS, TUNNEL_LIMIT)?;
+        Ok(child)
+    }
+
+    // 写到共享的页上时复制一份给自己，复制完能写才算处理了
+    pub fn copy_on_write(&mut self, address: Address) -> bool {
+        match self.memory.unshare(address >> PAGE_BITS) {
+            Ok(true) => self
+                .memory
+                .translate(address)
+                .map_or(false, |(_, a)| a.contains(MemoryRegionAttribute::Write)),
+            _ => false,
+        }
+    }
+
+    // 页表已经允许这样访问却还是出错了，是别的核心改过页表而本核心的 TLB 还没刷。刷掉再来一次就行
+    pub fn is_stale_fault(&self, address: Address, op: &MemoryOperation) -> bool {
+        let required = match op {
+            MemoryOperation::Read => MemoryRegionAttribute::Read,
+            MemoryOperation::Write => MemoryRegionAttribute::Write,
+            MemoryOperation::Execute => MemoryRegionAttribute::Execute,
+        };
+        let permitted = self
+            .memory
+            .translate(address)
+            .map_or(false, |(_, a)| a.contains(required));
+        if permitted {
+            self.memory.refresh(address >> PAGE_BITS);
+        }
+        permitted
+    }
+
     pub fn fill<A: Into<FlagSet<MemoryRegionAttribute>>>(
         &mut self,
         vpn: PageNumber,
@@ -155,6 +316,7 @@ impl Process {
         attributes: A,
         reserved: bool,
     ) -> Result<usize, ProcessMemoryError> {
+        self.check_page_limit(vpn, count)?;
         let flags = attrs_to_flags(attributes, reserved);
         self.memory
             .fill(vpn, count, flags)
@@ -173,16 +335,71 @@ impl Process {
         attributes: A,
         reserved: bool,
     ) -> Result<usize, ProcessMemoryError> {
+        // 映射的是别人的页，MemoryUnit 不计数，自己数
+        let fresh = self.count_unmapped(vpn, count);
+        if self.usage.page + fresh > self.limit.page {
+            return Err(ProcessMemoryError::OutOfMemory);
+        }
         let flags = attrs_to_flags(attributes, reserved);
         self.memory
             .map(vpn, ppn, count, flags)
-            .map(|p| {
-                self.usage.page += p;
-                p
+            .map(|_| {
+                self.usage.page += fresh;
+                fresh
             })
             .map_err(|e| ProcessMemoryError::from(e))
     }
 
+    // 线程栈、TLS 块和启动参数都算作栈
+    pub fn fill_stack(
+        &mut self,
+        vpn: PageNumber,
+        count: usize,
+    ) -> Result<usize, ProcessMemoryError> {
+        let fresh = self.count_unmapped(vpn, count);
+        if self.usage.stack + fresh * PAGE_SIZE > self.limit.stack {
+            return Err(ProcessMemoryError::OutOfMemory);
+        }
+        self.fill(
+            vpn,
+            count,
+            MemoryRegionAttribute::Write | MemoryRegionAttribute::Read,
+            false,
+        )
+        .map(|p| {
+            self.usage.stack += p * PAGE_SIZE;
+            p
+        })
+    }
+
+    pub fn free_stack(
+        &mut self,
+        vpn: PageNumber,
+        count: usize,
+    ) -> Result<usize, ProcessMemoryError> {
+        self.free(vpn, count).map(|p| {
+            self.usage.stack -= p * PAGE_SIZE;
+            p
+        })
+    }
+
+    // 已经映射的页不算，离上限还远时不用去数
+    fn check_page_limit(&self, vpn: PageNumber, count: usize) -> Result<(), ProcessMemoryError> {
+        if self.usage.page.saturating_add(count) <= self.limit.page
+            || self.usage.page + self.count_unmapped(vpn, count) <= self.limit.page
+        {
+            Ok(())
+        } else {
+            Err(ProcessMemoryError::OutOfMemory)
+        }
+    }
+
+    fn count_unmapped(&self, vpn: PageNumber, count: usize) -> usize {
+        (vpn..vpn.saturating_add(count))
+            .filter(|n| self.memory.translate(n << PAGE_BITS).is_none())
+            .count()
+    }
+
     pub fn free(&mut self, vpn: PageNumber, count: usize) -> Result<usize, ProcessMemoryError> {
         self.memory
             .free(vpn, count)
@@ -193,26 +410,259 @@ impl Process {
             .map_err(|e| ProcessMemoryError::from(e))
     }
 
+    // 在栈顶 top 之下给线程划出 TLS 块并按模板初始化，返回 (tp, 新的栈顶)。没有 TLS 段返回 None
+    pub fn setup_thread_local(
+        &mut self,
+        top: Address,
+    ) -> Result<Option<(Address, Address)>, ProcessMemoryError> {
+        if let Some(template) = &self.thread_local {
+            // RISC-V 的 TCB 大小为 0，tp 直接指向 TLS 块开头
+            let pointer = (top - template.memory_size) & !(template.align - 1);
+            let first = pointer >> PAGE_BITS;
+            let count = ((top - 1) >> PAGE_BITS) - first + 1;
+            let image = template.image.clone();
+            let length = template.memory_size;
+            self.fill_stack(first, count)?;
+            if length > 0 {
+                self.write(pointer, &image, length)?;
+            }
+            Ok(Some((pointer, pointer & !0xF)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn set_startup(&mut self, arguments: Vec<String>, variables: Vec<String>) {
+        self.arguments = arguments;
+        self.variables = variables;
+    }
+
+    // 在栈顶 top 之下依次放辅助向量、参数表、环境变量表和字符串，返回 (向量地址, 条目数, 新的栈顶)
+    pub fn setup_startup(
+        &mut self,
+        top: Address,
+        pid: Pid,
+        parent: Pid,
+    ) -> Result<(Address, usize, Address), ProcessMemoryError> {
+        let word = size_of::<usize>();
+        let entries = 8usize;
+        let strings = self
+            .arguments
+            .iter()
+            .chain(self.variables.iter())
+            .map(|s| s.len())
+            .sum::<usize>();
+        let tables = (self.arguments.len() + self.variables.len()) * 2 * word;
+        let base = (top - entries * 2 * word - tables - strings) & !0xF;
+        let argument_table = base + entries * 2 * word;
+        let variable_table = argument_table + self.arguments.len() * 2 * word;
+        let mut block = Vec::<u8>::with_capacity(top - base);
+        let vector = [
+            (AuxiliaryKey::Pid, pid as usize),
+            (AuxiliaryKey::Parent, parent as usize),
+            (AuxiliaryKey::PageSize, PAGE_SIZE),
+            (AuxiliaryKey::ArgumentCount, self.arguments.len()),
+            (AuxiliaryKey::Arguments, argument_table),
+            (AuxiliaryKey::VariableCount, self.variables.len()),
+            (AuxiliaryKey::Variables, variable_table),
+            (AuxiliaryKey::Null, 0),
+        ];
+        for (key, value) in vector {
+            block.extend_from_slice(&(key as usize).to_ne_bytes());
+            block.extend_from_slice(&value.to_ne_bytes());
+        }
+        let mut string = base + entries * 2 * word + tables;
+        for s in self.arguments.iter().chain(self.variables.iter()) {
+            block.extend_from_slice(&string.to_ne_bytes());
+            block.extend_from_slice(&s.len().to_ne_bytes());
+            string += s.len();
+        }
+        for s in self.arguments.iter().chain(self.variables.iter()) {
+            block.extend_from_slice(s.as_bytes());
+        }
+        let first = base >> PAGE_BITS;
+        let count = ((top - 1) >> PAGE_BITS) - first + 1;
+        self.fill_stack(first, count)?;
+        self.write(base, &block, 0)?;
+        self.startup = Some(base);
+        Ok((base, entries, base))
+    }
+
+    pub fn startup(&self) -> Option<Address> {
+        self.startup
+    }
+
+    // 只占住地址范围，页在第一次访问时才分配，所以页数上限要到那时才检查
     pub fn extend(&mut self, size: usize) -> Result<usize, ProcessMemoryError> {
-        if !size.is_power_of_two() {
+        let start = self.break_point + self.usage.heap;
+        // 堆不能长进 Map 的区域和栈里
+        if start.checked_add(size).map_or(true, |end| end > self.heap_ceiling())
+            || self.usage.heap + size > self.limit.heap
+        {
+            return Err(ProcessMemoryError::OutOfMemory);
+        }
+        self.usage.heap += size;
+        Ok(start + size)
+    }
+
+    // 堆和程序里全零的页第一次被访问时分配，页已经在的话说明是权限不对，返回 false
+    pub fn fill_lazy(&mut self, address: Address) -> bool {
+        if self.translate(address).is_some() {
+            return false;
+        }
+        let vpn = address >> PAGE_BITS;
+        let attributes =
+            if address >= self.break_point && address < self.break_point + self.usage.heap {
+                Some(MemoryRegionAttribute::Write | MemoryRegionAttribute::Read)
+            } else {
+                self.lazy
+                    .iter()
+                    .find(|r| r.start <= vpn && vpn < r.start + r.count)
+                    .map(|r| r.attributes)
+            };
+        if let Some(attributes) = attributes {
+            self.fill(vpn, 1, attributes, false).is_ok()
+        } else {
+            false
+        }
+    }
+
+    // 内核替进程读写时也要先把按需分配的页补上
+    fn ensure_resident(
+        &mut self,
+        address: Address,
+        length: usize,
+    ) -> Result<(), ProcessMemoryError> {
+        let mut page = address & !(PAGE_SIZE - 1);
+        while page < address + length {
+            if self.translate(page).is_none() && !self.fill_lazy(page) {
+                return Err(ProcessMemoryError::InaccessibleRegion);
+            }
+            page += PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    // Map 的区域在堆和栈之间，address 为 0 时从 map_ceiling 往下找空位
+    pub fn map_anonymous(
+        &mut self,
+        address: Address,
+        count: usize,
+        attributes: FlagSet<MemoryRegionAttribute>,
+    ) -> Result<Address, ProcessMemoryError> {
+        let start = self.place(address, count)?;
+        self.fill(start, count, attributes, false)?;
+        self.insert_mapping(start, count);
+        self.usage.mapped += count * PAGE_SIZE;
+        Ok(start << PAGE_BITS)
+    }
+
+    // 调用方保证物理页不在内存里，这些页不归进程所有也不会被回收
+    pub fn map_physical(
+        &mut self,
+        address: Address,
+        ppn: PageNumber,
+        count: usize,
+        attributes: FlagSet<MemoryRegionAttribute>,
+    ) -> Result<Address, ProcessMemoryError> {
+        let start = self.place(address, count)?;
+        self.map(start, ppn, count, attributes, false)?;
+        self.insert_mapping(start, count);
+        self.usage.mapped += count * PAGE_SIZE;
+        Ok(start << PAGE_BITS)
+    }
+
+    // 解除 Map 区域中的一段，或者从某页开始把堆的尾巴整个切掉，返回释放的页数
+    pub fn unmap(&mut self, address: Address, count: usize) -> Result<usize, ProcessMemoryError> {
+        if address & (PAGE_SIZE - 1) != 0 {
             return Err(ProcessMemoryError::MisalignedAddress);
         }
-        let start = self.break_point + self.usage.heap;
-        let count = (size + PAGE_SIZE - 1) >> PAGE_BITS;
-        let flags = attrs_to_flags(
-            MemoryRegionAttribute::Write | MemoryRegionAttribute::Read,
-            false,
-        );
-        match self.memory.fill(start >> PAGE_BITS, count, flags) {
-            Ok(pages) => {
-                self.usage.page += pages;
-                self.usage.heap += size;
-                Ok(start + size)
+        let start = address >> PAGE_BITS;
+        let end = start
+            .checked_add(count)
+            .ok_or(ProcessMemoryError::InaccessibleRegion)?;
+        let heap_end = self.break_point + self.usage.heap;
+        let heap_top = (heap_end + PAGE_SIZE - 1) >> PAGE_BITS;
+        if address >= self.break_point && address < heap_end && end >= heap_top {
+            let pages = heap_top - start;
+            let freed = self.free(start, pages)?;
+            self.usage.heap = address - self.break_point;
+            return Ok(freed);
+        }
+        if let Some(index) = self
+            .mappings
+            .iter()
+            .position(|m| m.start <= start && end <= m.start + m.count)
+        {
+            let freed = self.free(start, count)?;
+            let mapping = self.mappings.remove(index);
+            self.usage.mapped -= count * PAGE_SIZE;
+            // 从中间挖掉会分成两段
+            if mapping.start < start {
+                self.insert_mapping(mapping.start, start - mapping.start);
+            }
+            if end < mapping.start + mapping.count {
+                self.insert_mapping(end, mapping.start + mapping.count - end);
+            }
+            Ok(freed)
+        } else {
+            Err(ProcessMemoryError::InaccessibleRegion)
+        }
+    }
+
+    // 给出的地址要页对齐，落在堆顶和 map_ceiling 之间并且不和已有的区域重叠
+    fn place(&self, address: Address, count: usize) -> Result<PageNumber, ProcessMemoryError> {
+        let floor = (self.break_point + self.usage.heap + PAGE_SIZE - 1) >> PAGE_BITS;
+        let ceiling = self.map_ceiling >> PAGE_BITS;
+        if address == 0 {
+            let mut end = ceiling;
+            for m in self.mappings.iter().rev() {
+                if m.start + m.count + count <= end {
+                    break;
+                }
+                end = m.start;
+            }
+            if end < floor + count {
+                Err(ProcessMemoryError::OutOfMemory)
+            } else {
+                Ok(end - count)
+            }
+        } else if address & (PAGE_SIZE - 1) != 0 {
+            Err(ProcessMemoryError::MisalignedAddress)
+        } else {
+            let start = address >> PAGE_BITS;
+            match start.checked_add(count) {
+                Some(end) if start >= floor && end <= ceiling => {
+                    if self
+                        .mappings
+                        .iter()
+                        .any(|m| m.start < end && start < m.start + m.count)
+                    {
+                        Err(ProcessMemoryError::ConflictingMapping)
+                    } else {
+                        Ok(start)
+                    }
+                }
+                _ => Err(ProcessMemoryError::InaccessibleRegion),
             }
-            Err(error) => Err(ProcessMemoryError::from(error)),
         }
     }
 
+    fn insert_mapping(&mut self, start: PageNumber, count: usize) {
+        let index = self.mappings.partition_point(|m| m.start < start);
+        self.mappings.insert(index, Mapping { start, count });
+    }
+
+    fn heap_ceiling(&self) -> Address {
+        self.mappings
+            .first()
+            .map_or(self.map_ceiling, |m| m.start << PAGE_BITS)
+    }
+
+    pub fn set_map_ceiling(&mut self, ceiling: Address) {
+        self.map_ceiling = ceiling;
+    }
+
     pub fn write(
         &mut self,
         address: Address,
@@ -220,6 +670,16 @@ impl Process {
         length: usize,
     ) -> Result<usize, ProcessMemoryError> {
         let real_length = if length == 0 { data.len() } else { length };
+        Self::check_range(address, real_length)?;
+        self.ensure_resident(address, real_length)?;
+        // 内核直接写物理页，不会触发缺页，共享的页得先复制
+        let mut page = address & !(PAGE_SIZE - 1);
+        while page < address + real_length {
+            self.memory
+                .unshare(page >> PAGE_BITS)
+                .map_err(|e| ProcessMemoryError::from(e))?;
+            page += PAGE_SIZE;
+        }
         let mut written = 0usize;
         while written < real_length {
             if let Some(base) = self.translate(address + written) {
@@ -248,8 +708,15 @@ impl Process {
         Ok(written)
     }
 
-    pub fn read(&self, address: Address, length: usize) -> Result<Vec<u8>, ProcessMemoryError> {
-        let mut container = Vec::<u8>::with_capacity(length);
+    pub fn read(&mut self, address: Address, length: usize) -> Result<Vec<u8>, ProcessMemoryError> {
+        Self::check_range(address, length)?;
+        // 先确认每一页都映射了再分配，长度是用户随便填的
+        self.ensure_resident(address, length)?;
+        // 内核堆分配失败会直接停机，分不出来就当作内存不够
+        let mut container = Vec::<u8>::new();
+        container
+            .try_reserve_exact(length)
+            .map_err(|_| ProcessMemoryError::OutOfMemory)?;
         let mut read = 0usize;
         while read < length {
             if let Some(base) = self.translate(address + read) {
@@ -272,18 +739,59 @@ impl Process {
         Ok(container)
     }
 
+    // 用户给的地址只能落在用户空间里，TrapFrame 和跳板都不行
+    fn check_range(address: Address, length: usize) -> Result<(), ProcessMemoryError> {
+        if let Some(end) = address.checked_add(length)
+            && end <= PageEntryImpl::space_size()
+        {
+            Ok(())
+        } else {
+            Err(ProcessMemoryError::InaccessibleRegion)
+        }
+    }
+
     pub fn translate(&self, address: Address) -> Option<Address> {
         self.memory.translate(address).map(|(a, _)| a)
     }
 
-    pub fn page_table_token(&self) -> usize {
-        self.memory.satp()
+    // 每次回到用户态前取，ASID 可能换过了
+    pub fn page_table_token(&self, hart: HartId) -> usize {
+        self.memory.activate(hart)
     }
 
     pub fn has_permission(&self, perm: ProcessPermission) -> bool {
         self.permissions.contains(perm)
     }
 
+    pub fn permissions(&self) -> FlagSet<ProcessPermission> {
+        self.permissions
+    }
+
+    // 只能交给子进程父进程权限的子集，None 表示原样继承，空集就是除了 Valid 什么都没有
+    pub fn inherit_permissions(
+        &self,
+        requested: Option<FlagSet<ProcessPermission>>,
+    ) -> Result<FlagSet<ProcessPermission>, ProcessSpawnError> {
+        let Some(requested) = requested else {
+            return Ok(self.permissions);
+        };
+        if self.permissions.contains(requested) {
+            Ok(requested | ProcessPermission::Valid)
+        } else {
+            Err(ProcessSpawnError::InvalidPermissions)
+        }
+    }
+
+    pub fn set_permissions(&mut self, permissions: FlagSet<ProcessPermission>) {
+        self.permissions = permissions;
+    }
+
+    // 只减不增，Valid 总是保留
+    pub fn restrict(&mut self, kept: FlagSet<ProcessPermission>) -> FlagSet<ProcessPermission> {
+        self.permissions &= kept | ProcessPermission::Valid;
+        self.permissions
+    }
+
     pub fn stack_point(&self) -> Address {
         self.stack_point
     }
@@ -300,23 +808,33 @@ impl Process {
         self.tunnel_point
     }
 
-    pub fn tunnel_insert(&mut self, key: usize) -> Result<usize, ProcessTunnelError> {
-        if self.tunnels.len() < TUNNEL_LIMIT {
-            let mut last = 0usize;
-            for t in &self.tunnels {
-                if t.index() == last {
-                    last += 1;
-                } else {
-                    break;
-                }
+    // 返回窗口中第一个能连续放下 count 页的槽位，窗口一共 TUNNEL_LIMIT 页
+    pub fn tunnel_insert(
+        &mut self,
+        key: usize,
+        count: usize,
+    ) -> Result<usize, ProcessTunnelError> {
+        let mut last = 0usize;
+        let mut position = 0usize;
+        for t in &self.tunnels {
+            if t.index() >= last + count {
+                break;
             }
-            self.tunnels.insert(last, Endpoint::new(last, key));
+            last = t.index() + t.count();
+            position += 1;
+        }
+        if last + count <= TUNNEL_LIMIT {
+            self.tunnels.insert(position, Endpoint::new(last, count, key));
             Ok(last)
         } else {
             Err(ProcessTunnelError::ReachLimit)
         }
     }
 
+    pub fn tunnel_keys(&self) -> Vec<usize> {
+        self.tunnels.iter().map(|t| t.key()).collect()
+    }
+
     pub fn tunnel_eject(&mut self, key: usize) -> bool {
         if let Some(index) = self.tunnels.iter().position(|t| t.key() == key) {
             self.tunnels.remove(index);
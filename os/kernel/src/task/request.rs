@@ -0,0 +1,171 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::vec::Vec;
+use erhino_shared::{
+    mem::Address,
+    proc::{Pid, Rid, Tid},
+    sync::spin::SimpleLock,
+};
+use lock_api::Mutex;
+
+use crate::hart;
+
+// 异步系统调用排队中的请求。事件方只会把请求标记为 fed，不会去碰其他进程的线程（避免互相持有进程锁），
+// 调度器在挑选线程时检查其 Pending(rid) 对应的请求是否已经 fed，再把线程转为 Fed 重新加入调度。
+static REQUESTS: Mutex<SimpleLock, Vec<KernelRequest>> = Mutex::new(Vec::new());
+static RID_GENERATOR: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelRequestKind {
+    // 等子进程退出，0 表示任意一个
+    ChildExit(Pid),
+    // 等同一进程里的线程退出
+    ThreadExit(Tid),
+    // 等消息投进本进程的信箱
+    MessageArrival,
+    // 发送方等目标进程的信箱腾出位置
+    MailboxSpace(Pid),
+    // 等目标进程回复调用
+    Reply(Pid),
+    // 挂在某个核心的定时队列里睡到期限
+    Sleep,
+    // 等通道对端发来中断
+    TunnelInterrupt(usize),
+    // 在用户虚拟地址上等唤醒
+    Futex(Address),
+}
+
+pub struct KernelRequest {
+    id: Rid,
+    pid: Pid,
+    tid: Tid,
+    kind: KernelRequestKind,
+    fed: bool,
+    response: Option<Vec<u8>>,
+}
+
+impl KernelRequest {
+    pub fn id(&self) -> Rid {
+        self.id
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn tid(&self) -> Tid {
+        self.tid
+    }
+
+    pub fn kind(&self) -> KernelRequestKind {
+        self.kind
+    }
+
+    // answer 附带的数据，没带数据就被喂了的是 None
+    pub fn take_response(&mut self) -> Option<Vec<u8>> {
+        self.response.take()
+    }
+}
+
+pub fn issue(pid: Pid, tid: Tid, kind: KernelRequestKind) -> Rid {
+    let id = RID_GENERATOR.fetch_add(1, Ordering::Relaxed);
+    REQUESTS.lock().push(KernelRequest {
+        id,
+        pid,
+        tid,
+        kind,
+        fed: false,
+        response: None,
+    });
+    id
+}
+
+// 把符合条件的未完成请求都标记为已喂，返回个数
+pub fn resolve<F: Fn(&KernelRequest) -> bool>(pred: F) -> usize {
+    let mut count = 0usize;
+    let mut requests = REQUESTS.lock();
+    for request in requests.iter_mut().filter(|r| !r.fed) {
+        if pred(request) {
+            request.fed = true;
+            count += 1;
+        }
+    }
+    drop(requests);
+    if count > 0 {
+        hart::app::awake_idle();
+    }
+    count
+}
+
+// 按登记先后标记符合条件的未完成请求，最多 limit 个
+pub fn resolve_at_most<F: Fn(&KernelRequest) -> bool>(limit: usize, pred: F) -> usize {
+    let mut count = 0usize;
+    let mut requests = REQUESTS.lock();
+    // swap_remove 打乱了顺序，按 id 挑最早登记的
+    while count < limit {
+        if let Some(request) = requests
+            .iter_mut()
+            .filter(|r| !r.fed && pred(r))
+            .min_by_key(|r| r.id)
+        {
+            request.fed = true;
+            count += 1;
+        } else {
+            break;
+        }
+    }
+    drop(requests);
+    if count > 0 {
+        hart::app::awake_idle();
+    }
+    count
+}
+
+// 带着数据喂指定的请求，找不到或者已经喂过了返回 false
+pub fn answer<F: Fn(&KernelRequest) -> bool>(id: Rid, pred: F, response: Vec<u8>) -> bool {
+    let mut requests = REQUESTS.lock();
+    if let Some(request) = requests
+        .iter_mut()
+        .find(|r| r.id == id && !r.fed && pred(r))
+    {
+        request.fed = true;
+        request.response = Some(response);
+        drop(requests);
+        hart::app::awake_idle();
+        true
+    } else {
+        false
+    }
+}
+
+pub fn is_fed(id: Rid) -> bool {
+    REQUESTS.lock().iter().any(|r| r.id == id && r.fed)
+}
+
+pub fn is_pending(id: Rid) -> bool {
+    REQUESTS.lock().iter().any(|r| r.id == id && !r.fed)
+}
+
+// 从表里拿走请求，被喂过的线程回来完成调用时用
+pub fn take(id: Rid) -> Option<KernelRequest> {
+    let mut requests = REQUESTS.lock();
+    if let Some(index) = requests.iter().position(|r| r.id == id) {
+        Some(requests.swap_remove(index))
+    } else {
+        None
+    }
+}
+
+pub fn cancel(id: Rid) {
+    REQUESTS.lock().retain(|r| r.id != id);
+}
+
+// 丢掉该线程登记的所有请求
+pub fn cancel_thread(pid: Pid, tid: Tid) {
+    REQUESTS.lock().retain(|r| r.pid != pid || r.tid != tid);
+}
+
+// 丢掉该进程登记的所有请求
+pub fn cancel_all(pid: Pid) {
+    REQUESTS.lock().retain(|r| r.pid != pid);
+}
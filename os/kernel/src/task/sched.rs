@@ -1,31 +1,53 @@
 use alloc::vec::Vec;
 use erhino_shared::{
     mem::Address,
-    proc::{Pid, Tid},
+    proc::{ExitCode, Pid, Rid, Tid},
+    time::Timestamp,
 };
 
 use crate::{mm::ProcessAddressRegion, trap::TrapFrame};
 
-use super::{proc::Process, thread::Thread};
+use super::{
+    proc::{Process, ProcessHealth},
+    thread::Thread,
+};
 
 pub mod enough;
 pub mod unfair;
 
+pub enum ThreadJoinState {
+    NotFound,
+    Living,
+    Exited(ExitCode),
+}
+
 pub trait ScheduleContext {
     fn pid(&self) -> Pid;
+    fn parent(&self) -> Pid;
     fn tid(&self) -> Tid;
     fn process(&self) -> &mut Process;
     fn thread(&self) -> &mut Thread;
-    fn trapframe(&self) -> &'static mut TrapFrame;
-    fn add_proc(&self, proc: Process) -> Pid;
-    fn add_thread(&self, thread: Thread) -> Tid;
+    fn trapframe(&self) -> Option<&'static mut TrapFrame>;
+    fn add_proc(&self, proc: Process) -> Option<Pid>;
+    // 复制当前进程，子进程只有当前线程一个线程
+    fn fork(&self) -> Option<Pid>;
+    fn remove_proc(&self, pid: Pid) -> bool;
+    fn parent_of(&self, pid: Pid) -> Option<Pid>;
+    fn children(&self) -> Vec<(Pid, ProcessHealth)>;
+    fn children_of(&self, pid: Pid) -> Vec<(Pid, ProcessHealth)>;
+    fn add_thread(&self, thread: Thread) -> Option<Tid>;
+    fn kill_thread(&self, tid: Tid, code: ExitCode) -> bool;
+    fn join_thread(&self, tid: Tid) -> ThreadJoinState;
     fn schedule(&mut self);
+    fn sleep(&mut self, deadline: Timestamp, rid: Rid);
+    // 替换进程的闹钟，None 表示取消
+    fn alarm(&mut self, deadline: Option<Timestamp>);
     fn find<F: FnMut(&mut Process)>(&self, pid: Pid, action: F) -> bool;
 }
 
 pub trait Scheduler {
     type Context: ScheduleContext;
-    fn add(proc: Process, parent: Option<Pid>) -> Pid;
+    fn add(proc: Process, parent: Option<Pid>) -> Option<Pid>;
     fn find<F: FnMut(&mut Process)>(pid: Pid, action: F) -> bool;
     fn snapshot() -> Vec<Pid>;
     fn is_address_in(&self, addr: Address) -> Option<ProcessAddressRegion>;
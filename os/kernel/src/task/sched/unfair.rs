@@ -6,11 +6,12 @@ use alloc::{
 };
 use erhino_shared::{
     mem::{Address, MemoryRegionAttribute, PageNumber},
-    proc::{ExecutionState, Pid, Tid},
+    proc::{ExecutionState, ExitCode, Pid, Rid, SignalMap, SystemSignal, Tid},
     sync::spin::SimpleLock,
+    time::{Timestamp, NS_PER_MS},
 };
 use flagset::FlagSet;
-use lock_api::RawMutex;
+use lock_api::{Mutex, RawMutex};
 
 use crate::{
     external::_user_trap,
@@ -23,13 +24,14 @@ use crate::{
     sync::up::UpSafeCell,
     task::{
         proc::{Process, ProcessHealth},
+        request::{self, KernelRequestKind},
         thread::Thread,
     },
-    timer::Timer,
+    timer::{self, Timer},
     trap::TrapFrame,
 };
 
-use super::{ScheduleContext, Scheduler};
+use super::{ScheduleContext, Scheduler, ThreadJoinState};
 
 type Shared<T> = UpSafeCell<T>;
 
@@ -37,14 +39,21 @@ type Shared<T> = UpSafeCell<T>;
 const QUANTUM: usize = 20;
 
 static mut PROC_TABLE: ProcessTable = ProcessTable::new();
+// 所有核心共用的闹钟，每个进程最多一项，哪个核心先到期就由哪个核心发信号
+static ALARMS: Mutex<SimpleLock, Vec<(Timestamp, Pid)>> = Mutex::new(Vec::new());
 
 const THREAD_STACK_SIZE: usize = 8 * 1024 * 1024;
+// 栈从 stack_point 向下排，线程太多会一路压到堆上
+const THREAD_LIMIT: usize = 256;
 
 pub struct UnfairContext {
     hartid: HartId,
     process: Arc<Shared<ProcessCell>>,
     thread: Arc<Shared<ThreadCell>>,
     scheduled: bool,
+    sleep: Option<(Timestamp, Rid)>,
+    // Some(None) 表示取消闹钟
+    alarm: Option<Option<Timestamp>>,
 }
 
 impl ScheduleContext for UnfairContext {
@@ -52,6 +61,10 @@ impl ScheduleContext for UnfairContext {
         self.process.id
     }
 
+    fn parent(&self) -> Pid {
+        self.process.parent
+    }
+
     fn tid(&self) -> Tid {
         self.thread.id
     }
@@ -64,23 +77,107 @@ impl ScheduleContext for UnfairContext {
         &mut self.thread.get_mut().inner
     }
 
-    fn trapframe(&self) -> &'static mut TrapFrame {
+    fn trapframe(&self) -> Option<&'static mut TrapFrame> {
         self.process.struct_at(self.thread.trapframe)
     }
 
-    fn add_proc(&self, proc: Process) -> Pid {
+    fn add_proc(&self, proc: Process) -> Option<Pid> {
         let table = unsafe { &mut PROC_TABLE };
-        table.add(proc, Some(self.process.id))
+        let pid = table.add(proc, Some(self.process.id))?;
+        hart::app::awake_idle();
+        Some(pid)
     }
 
-    fn add_thread(&self, thread: Thread) -> Tid {
+    fn fork(&self) -> Option<Pid> {
+        let table = unsafe { &mut PROC_TABLE };
+        let pid = table.fork(&self.process, &self.thread)?;
+        hart::app::awake_idle();
+        Some(pid)
+    }
+
+    fn remove_proc(&self, pid: Pid) -> bool {
+        let table = unsafe { &mut PROC_TABLE };
+        ALARMS.lock().retain(|(_, p)| *p != pid);
+        table.remove(pid).is_some()
+    }
+
+    fn children(&self) -> Vec<(Pid, ProcessHealth)> {
+        self.children_of(self.process.id)
+    }
+
+    fn children_of(&self, pid: Pid) -> Vec<(Pid, ProcessHealth)> {
+        let table = unsafe { &PROC_TABLE };
+        // 不去获取子进程的 state_lock，子进程退出时会持有自己的锁去找父进程
+        table.collect(|p| {
+            if p.id != pid && p.parent == pid {
+                Some((p.id, p.inner.health))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn add_thread(&self, thread: Thread) -> Option<Tid> {
         self.process.get_mut().add(thread)
     }
 
+    fn kill_thread(&self, tid: Tid, code: ExitCode) -> bool {
+        if let Some(t) = self.process.find_thread(tid) {
+            if t.inner.state == ExecutionState::Dead {
+                return false;
+            }
+            let thread = t.get_mut();
+            thread.inner.state = ExecutionState::Dead;
+            thread.inner.exit_code = code;
+            request::cancel_thread(self.process.id, tid);
+            // 没有在任何核心上运行就地回收，否则等它所在的核心把它调度出去时再回收
+            if t.run_lock.try_lock() {
+                self.process.get_mut().release_thread(tid);
+                unsafe { t.run_lock.unlock() };
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn join_thread(&self, tid: Tid) -> ThreadJoinState {
+        if let Some(t) = self.process.find_thread(tid) {
+            // 拿得到 run_lock 说明它已经离开了核心，栈也已经被回收，可以安全地摘除
+            if t.inner.state == ExecutionState::Dead && t.run_lock.try_lock() {
+                let code = t.inner.exit_code;
+                self.process.get_mut().remove(tid);
+                unsafe { t.run_lock.unlock() };
+                ThreadJoinState::Exited(code)
+            } else {
+                ThreadJoinState::Living
+            }
+        } else {
+            ThreadJoinState::NotFound
+        }
+    }
+
     fn schedule(&mut self) {
         self.scheduled = true;
     }
 
+    fn sleep(&mut self, deadline: Timestamp, rid: Rid) {
+        self.sleep = Some((deadline, rid));
+    }
+
+    fn alarm(&mut self, deadline: Option<Timestamp>) {
+        self.process().signal.set_alarm(deadline);
+        self.alarm = Some(deadline);
+    }
+
+    fn parent_of(&self, pid: Pid) -> Option<Pid> {
+        if self.process.id == pid {
+            Some(self.process.parent)
+        } else {
+            unsafe { &PROC_TABLE }.find_process(pid).map(|p| p.parent)
+        }
+    }
+
     fn find<F: FnMut(&mut Process)>(&self, pid: Pid, mut action: F) -> bool {
         if self.process.id == pid {
             action(self.process());
@@ -105,6 +202,8 @@ struct ProcessLayout {
     stack_point: Address,
     break_point: Address,
     thread_count: usize,
+    // fork 出来的进程栈区从调用线程的栈开始，能放下的线程少一些
+    thread_limit: usize,
 }
 
 impl ProcessLayout {
@@ -114,6 +213,7 @@ impl ProcessLayout {
             stack_point: stack,
             break_point: heap,
             thread_count: 1,
+            thread_limit: THREAD_LIMIT,
         }
     }
 
@@ -127,6 +227,9 @@ impl ProcessLayout {
             AddressSpace::User => {
                 if addr < self.break_point {
                     ProcessAddressRegion::Program
+                } else if addr >= self.stack_point {
+                    // 栈上面是隧道，或者是 fork 时丢掉的其他线程的栈
+                    ProcessAddressRegion::Unknown
                 } else {
                     let diff = (self.stack_point - addr - 1) / THREAD_STACK_SIZE;
                     let count = self.thread_count;
@@ -159,8 +262,11 @@ const TRAPFRAME_SIZE: usize = 1024;
 const TRAPFRAME_HOLD: usize = PAGE_SIZE / TRAPFRAME_SIZE;
 
 impl ProcessCell {
-    pub fn new(proc: Process, pid: Pid, parent: Pid, layout: ProcessLayout) -> Self {
+    // 没有页给跳板的页表时返回 None
+    pub fn new(proc: Process, pid: Pid, parent: Pid, layout: ProcessLayout) -> Option<Self> {
         let mut mutable = proc;
+        // Map 的区域在所有线程的栈下面
+        mutable.set_map_ceiling(layout.stack_point - layout.thread_limit * THREAD_STACK_SIZE);
         mutable
             .map(
                 layout.trampoline >> PAGE_BITS,
@@ -171,8 +277,8 @@ impl ProcessCell {
                     | MemoryRegionAttribute::Read,
                 true,
             )
-            .expect("spawn process cell but no frame available for trampoline");
-        Self {
+            .ok()?;
+        Some(Self {
             inner: mutable,
             id: pid,
             parent,
@@ -183,7 +289,7 @@ impl ProcessCell {
             prev: None,
             ring_lock: SimpleLock::new(),
             state_lock: SimpleLock::new(),
-        }
+        })
     }
 
     pub fn address_of_trapframe<E: PageTableEntry>(trampoline: Address, id: Tid) -> Address {
@@ -264,29 +370,63 @@ impl ProcessCell {
         }
     }
 
-    pub fn add(&mut self, thread: Thread) -> Tid {
+    // 线程数到上限或者没有页放 TrapFrame、TLS 块时返回 None
+    pub fn add(&mut self, thread: Thread) -> Option<Tid> {
         let option = self.find_gap();
         let tid = if let Some(gap) = &option {
             gap.id + 1
         } else {
             0 as Tid
         };
+        if tid as usize >= self.layout.thread_limit {
+            return None;
+        }
         let generation = unsafe { &PROC_TABLE }.gen();
         let trapframe = Self::address_of_trapframe::<PageEntryImpl>(self.layout.trampoline, tid);
-        let stack = ProcessCell::address_of_stack(self.layout.stack_point, tid);
+        let mut stack = ProcessCell::address_of_stack(self.layout.stack_point, tid);
         let entry = thread.entry_point;
         let mut cell = ThreadCell::new(thread, tid, generation, trapframe);
-        self.ensure_page_created(
+        if !self.ensure_page_created(
             trapframe >> PAGE_BITS,
             MemoryRegionAttribute::Write | MemoryRegionAttribute::Read,
             true,
-        );
-        self.struct_at::<TrapFrame>(trapframe).init(
+        ) {
+            return None;
+        }
+        // 栈的缺页要靠 thread_count 认出来
+        if tid as usize >= self.layout.thread_count {
+            self.layout.thread_count = tid as usize + 1;
+        }
+        // 有 TLS 的程序 tp 指向栈顶划出的 TLS 块，栈从块下面开始；没有的沿用 tid
+        let mut thread_pointer = tid as Address;
+        let mut top = self.layout.stack_point - tid as usize * THREAD_STACK_SIZE;
+        match self.inner.setup_thread_local(top) {
+            Ok(Some((pointer, below))) => {
+                thread_pointer = pointer;
+                top = below;
+                stack = below;
+            }
+            Ok(None) => {}
+            Err(_) => return None,
+        }
+        // 主线程拿到辅助向量的条目数和地址，其他线程拿到创建时给的参数和自己的 tid
+        let registers = if tid == 0 {
+            match self.inner.setup_startup(top, self.id, self.parent) {
+                Ok((vector, count, below)) => {
+                    stack = below;
+                    [count as u64, vector as u64]
+                }
+                Err(_) => return None,
+            }
+        } else {
+            [cell.inner.argument as u64, tid as u64]
+        };
+        self.struct_at::<TrapFrame>(trapframe)?.init(
             entry,
             stack,
             self.layout.trampoline,
-            tid,
-            [self.id as u64, self.parent as u64],
+            thread_pointer,
+            registers,
         );
         if let Some(gap) = &option {
             gap.ring_lock.lock();
@@ -300,28 +440,123 @@ impl ProcessCell {
             self.head = Some(Arc::new(Shared::new(cell)));
             unsafe { self.head_lock.unlock() };
         }
-        tid
+        Some(tid)
+    }
+
+    // fork 出来的主线程接着父进程调用线程的 TrapFrame 跑，Fork 在这边返回 0。cell 还没加入进程表
+    pub fn adopt(&mut self, thread: Thread, source: &TrapFrame) -> Option<Tid> {
+        let generation = unsafe { &PROC_TABLE }.gen();
+        let trapframe = Self::address_of_trapframe::<PageEntryImpl>(self.layout.trampoline, 0);
+        if !self.ensure_page_created(
+            trapframe >> PAGE_BITS,
+            MemoryRegionAttribute::Write | MemoryRegionAttribute::Read,
+            true,
+        ) {
+            return None;
+        }
+        let frame = self.struct_at::<TrapFrame>(trapframe)?;
+        frame.duplicate(source);
+        frame.x[10] = 0;
+        frame.x[11] = 0;
+        // 子进程在 x12 里拿到自己的 pid
+        frame.x[12] = self.id as u64;
+        frame.move_next_instruction();
+        let cell = ThreadCell::new(thread, 0, generation, trapframe);
+        self.head_lock.lock();
+        self.head = Some(Arc::new(Shared::new(cell)));
+        unsafe { self.head_lock.unlock() };
+        Some(0)
+    }
+
+    pub fn find_thread(&self, tid: Tid) -> Option<Arc<Shared<ThreadCell>>> {
+        self.head_lock.lock();
+        if let Some(head) = &self.head {
+            unsafe { self.head_lock.unlock() };
+            let mut current = head.clone();
+            while current.id != tid {
+                if let Some(next) = self.move_next(&current) {
+                    current = next;
+                } else {
+                    return None;
+                }
+            }
+            Some(current)
+        } else {
+            unsafe { self.head_lock.unlock() };
+            None
+        }
+    }
+
+    pub fn remove(&mut self, tid: Tid) -> Option<Arc<Shared<ThreadCell>>> {
+        self.head_lock.lock();
+        let head = self.head.clone();
+        if let Some(head) = head {
+            if head.id == tid {
+                head.ring_lock.lock();
+                self.head = head.get_mut().next.take();
+                unsafe {
+                    head.ring_lock.unlock();
+                    self.head_lock.unlock();
+                }
+                Some(head)
+            } else {
+                unsafe { self.head_lock.unlock() };
+                let mut current = head;
+                loop {
+                    current.ring_lock.lock();
+                    if let Some(next) = current.next.clone() {
+                        if next.id == tid {
+                            next.ring_lock.lock();
+                            current.get_mut().next = next.get_mut().next.take();
+                            unsafe {
+                                next.ring_lock.unlock();
+                                current.ring_lock.unlock();
+                            }
+                            return Some(next);
+                        } else {
+                            unsafe { current.ring_lock.unlock() };
+                            current = next;
+                        }
+                    } else {
+                        unsafe { current.ring_lock.unlock() };
+                        return None;
+                    }
+                }
+            }
+        } else {
+            unsafe { self.head_lock.unlock() };
+            None
+        }
     }
 
+    // 线程死亡且离开核心后回收它的栈，并唤醒等待它的线程。调用方需持有 state_lock
+    pub fn release_thread(&mut self, tid: Tid) {
+        let top = self.layout.stack_point - tid as usize * THREAD_STACK_SIZE;
+        self.inner
+            .free_stack(
+                (top - THREAD_STACK_SIZE) >> PAGE_BITS,
+                THREAD_STACK_SIZE >> PAGE_BITS,
+            )
+            .expect("free the stack of a dead thread failed");
+        let pid = self.id;
+        request::resolve(|r| r.pid() == pid && r.kind() == KernelRequestKind::ThreadExit(tid));
+    }
+
+    // 调用方需持有 state_lock 或者独占这个 cell（还没加入进程表）
     pub fn ensure_page_created<A: Into<FlagSet<MemoryRegionAttribute>> + Copy>(
         &mut self,
         number: PageNumber,
         attributes: A,
         reserved: bool,
-    ) {
-        self.state_lock.lock();
-        self.inner
-            .fill(number, 1, attributes, reserved)
-            .expect("process memory for scheduling create failed");
-        unsafe { self.state_lock.unlock() };
+    ) -> bool {
+        self.inner.fill(number, 1, attributes, reserved).is_ok()
     }
 
-    pub fn struct_at<'context, T: Sized>(&self, addr: Address) -> &'context mut T {
-        let physical = self
-            .inner
+    // 页还没建出来的时候为 None
+    pub fn struct_at<'context, T: Sized>(&self, addr: Address) -> Option<&'context mut T> {
+        self.inner
             .translate(addr)
-            .expect("the page struct at has not been created");
-        unsafe { &mut *(physical as *mut T) }
+            .map(|physical| unsafe { &mut *(physical as *mut T) })
     }
 }
 
@@ -406,7 +641,7 @@ impl ProcessTable {
         self.pid_generator.fetch_add(1, Ordering::Relaxed) as Pid
     }
 
-    pub fn add(&mut self, proc: Process, parent: Option<Pid>) -> Pid {
+    pub fn add(&mut self, proc: Process, parent: Option<Pid>) -> Option<Pid> {
         let pid = self.new_pid();
         let parent_id = if let Some(parent) = parent {
             parent
@@ -418,11 +653,49 @@ impl ProcessTable {
             proc.stack_point(),
             proc.break_point(),
         );
-        let main = Thread::new(proc.entry_point());
-        let mut cell = ProcessCell::new(proc, pid, parent_id, layout);
-        cell.add(main);
+        let main = Thread::new(proc.entry_point(), 0);
+        let mut cell = ProcessCell::new(proc, pid, parent_id, layout)?;
+        cell.add(main)?;
+        self.add_cell(cell);
+        Some(pid)
+    }
+
+    // 调用线程的栈成为子进程主线程的栈，其他线程的栈在子进程里都释放掉。调用方持有父进程的 state_lock
+    pub fn fork(
+        &mut self,
+        parent: &Arc<Shared<ProcessCell>>,
+        thread: &Arc<Shared<ThreadCell>>,
+    ) -> Option<Pid> {
+        let proc = parent.get_mut().inner.fork().ok()?;
+        let pid = self.new_pid();
+        let offset = thread.id as usize * THREAD_STACK_SIZE;
+        let mut layout = ProcessLayout::new(
+            parent.layout.trampoline,
+            parent.layout.stack_point - offset,
+            parent.layout.break_point,
+        );
+        layout.thread_limit = parent.layout.thread_limit - thread.id as usize;
+        let top = layout.stack_point;
+        let floor = top - layout.thread_limit * THREAD_STACK_SIZE;
+        // 主线程栈顶上的辅助向量、参数和 TLS 块留着，其余的栈都释放
+        let kept = proc
+            .startup()
+            .map_or(parent.layout.stack_point, |s| s & !(PAGE_SIZE - 1))
+            .max(top);
+        let mut cell = ProcessCell::new(proc, pid, parent.id, layout)?;
+        cell.inner
+            .free_stack(top >> PAGE_BITS, (kept - top) >> PAGE_BITS)
+            .ok()?;
+        cell.inner
+            .free_stack(
+                floor >> PAGE_BITS,
+                (top - THREAD_STACK_SIZE - floor) >> PAGE_BITS,
+            )
+            .ok()?;
+        let main = Thread::new(thread.inner.entry_point, thread.inner.argument);
+        cell.adopt(main, parent.struct_at::<TrapFrame>(thread.trapframe)?)?;
         self.add_cell(cell);
-        pid
+        Some(pid)
     }
 
     fn add_cell(&mut self, mut cell: ProcessCell) {
@@ -450,6 +723,57 @@ impl ProcessTable {
         }
     }
 
+    pub fn remove(&mut self, pid: Pid) -> Option<Arc<Shared<ProcessCell>>> {
+        if let Some(cell) = self.find_process(pid) {
+            self.last_lock.lock();
+            self.head_lock.lock();
+            cell.ring_lock.lock();
+            let mutable = cell.get_mut();
+            let next = mutable.next.take();
+            let prev = mutable.prev.take().and_then(|w| w.upgrade());
+            unsafe { cell.ring_lock.unlock() };
+            if let Some(n) = &next {
+                n.ring_lock.lock();
+                n.get_mut().prev = prev.as_ref().map(|p| Arc::downgrade(p));
+                unsafe { n.ring_lock.unlock() };
+            } else {
+                self.last = prev.as_ref().map(|p| Arc::downgrade(p));
+            }
+            if let Some(p) = &prev {
+                p.ring_lock.lock();
+                p.get_mut().next = next;
+                unsafe { p.ring_lock.unlock() };
+            } else {
+                self.head = next;
+            }
+            unsafe {
+                self.head_lock.unlock();
+                self.last_lock.unlock();
+            }
+            Some(cell)
+        } else {
+            None
+        }
+    }
+
+    pub fn collect<T, F: Fn(&ProcessCell) -> Option<T>>(&self, filter: F) -> Vec<T> {
+        let mut result = Vec::<T>::new();
+        self.head_lock.lock();
+        if let Some(head) = &self.head {
+            unsafe { self.head_lock.unlock() };
+            let mut current = Some(head.clone());
+            while let Some(p) = current {
+                if let Some(item) = filter(&p) {
+                    result.push(item);
+                }
+                current = self.move_next_process(&p, false)
+            }
+        } else {
+            unsafe { self.head_lock.unlock() };
+        }
+        result
+    }
+
     pub fn move_next_process(
         &self,
         current: &Arc<Shared<ProcessCell>>,
@@ -512,7 +836,7 @@ impl ProcessTable {
                     return Some((next_proc.clone(), next_thread.clone()));
                 } else {
                     unsafe { next_proc.head_lock.unlock() };
-                    next_proc_option = self.move_next_process(proc, true);
+                    next_proc_option = self.move_next_process(next_proc, true);
                 }
             }
             None
@@ -561,6 +885,8 @@ pub struct UnfairScheduler<T> {
     hartid: HartId,
     timer: T,
     current: Option<(Arc<Shared<ProcessCell>>, Arc<Shared<ThreadCell>>)>,
+    // 本核心上睡下去的线程，到期后喂给对应的请求，线程醒来可能在任何一个核心
+    sleepers: Vec<(Timestamp, Rid)>,
 }
 
 impl<T: Timer> UnfairScheduler<T> {
@@ -569,33 +895,110 @@ impl<T: Timer> UnfairScheduler<T> {
             hartid,
             timer,
             current: None,
+            sleepers: Vec::new(),
+        }
+    }
+
+    fn wake_sleepers(&mut self) {
+        let now = timer::monotonic();
+        let mut rung = Vec::<(Timestamp, Pid)>::new();
+        // 先摘下到期的再去拿进程的锁，with_context 是拿着进程的锁来改闹钟的
+        ALARMS.lock().retain(|(deadline, pid)| {
+            if *deadline <= now {
+                rung.push((*deadline, *pid));
+                false
+            } else {
+                true
+            }
+        });
+        for (deadline, pid) in rung {
+            if let Some(p) = unsafe { &PROC_TABLE }.find_process(pid) {
+                p.state_lock.lock();
+                let process = &mut p.get_mut().inner;
+                if process.health == ProcessHealth::Healthy
+                    && process.signal.alarm() == Some(deadline)
+                {
+                    process.signal.set_alarm(None);
+                    process.signal.enqueue(SystemSignal::Alarm as SignalMap);
+                }
+                unsafe { p.state_lock.unlock() };
+            }
+        }
+        let mut expired = Vec::<Rid>::new();
+        // 提前被叫醒、取消或者随线程一起没了的请求不用再等它到期
+        self.sleepers.retain(|(deadline, rid)| {
+            if !request::is_pending(*rid) {
+                false
+            } else if *deadline <= now {
+                expired.push(*rid);
+                false
+            } else {
+                true
+            }
+        });
+        if !expired.is_empty() {
+            request::resolve(|r| expired.contains(&r.id()));
         }
     }
 
+    // 距离最早的闹钟还有多少毫秒，向上取整
+    fn next_alarm(&self) -> Option<usize> {
+        let alarm = ALARMS.lock().iter().map(|(deadline, _)| *deadline).min();
+        self.sleepers
+            .iter()
+            .map(|(deadline, _)| *deadline)
+            .chain(alarm)
+            .min()
+            .map(|deadline| {
+                let now = timer::monotonic();
+                if deadline > now {
+                    ((deadline - now + NS_PER_MS - 1) / NS_PER_MS) as usize
+                } else {
+                    0
+                }
+            })
+    }
+
     fn find_next(&self) -> Option<(Arc<Shared<ProcessCell>>, Arc<Shared<ThreadCell>>)> {
         let table = unsafe { &PROC_TABLE };
         let pred: fn(&Arc<Shared<ProcessCell>>, &Arc<Shared<ThreadCell>>) -> bool = |p, t| {
             let mut pass = false;
             p.state_lock.lock();
             if p.inner.health == ProcessHealth::Healthy {
-                if t.inner.state == ExecutionState::Ready && t.run_lock.try_lock() {
+                if let ExecutionState::Pending(rid) = t.inner.state
+                    && request::is_fed(rid)
+                {
+                    t.get_mut().inner.state = ExecutionState::Fed(rid);
+                }
+                let runnable = match t.inner.state {
+                    ExecutionState::Ready | ExecutionState::Fed(_) => true,
+                    _ => false,
+                };
+                if runnable && t.run_lock.try_lock() {
                     let thread = t.get_mut();
                     // 如果是主线程，不在处理信号且有信号要处理则获得优先权无视代数判定（但会增加代数
+                    // Fed 的线程要先回去完成它的系统调用，不能被信号打断
                     if t.id == 0
-                        && p.inner.signal.has_pending()
+                        && t.inner.state == ExecutionState::Ready
                         && !p.inner.signal.is_handling()
-                        && p.inner.signal.has_handler()
+                        && p.inner.signal.has_deliverable()
+                        && let Some(trapframe) = p.struct_at::<TrapFrame>(t.trapframe)
+                        && let Some((signal, argument, handler)) =
+                            p.get_mut().inner.signal.dequeue()
                     {
                         let process = p.get_mut();
-                        let trapframe = p.struct_at::<TrapFrame>(t.trapframe);
                         process.inner.signal.backup(trapframe);
-                        trapframe.x[10] = process.inner.signal.dequeue();
-                        trapframe.pc = p.inner.signal.handler().unwrap() as u64;
+                        trapframe.x[10] = signal;
+                        trapframe.x[11] = argument.0 as u64;
+                        trapframe.x[12] = argument.1 as u64;
+                        trapframe.pc = handler as u64;
                         thread.grow();
                         thread.inner.state = ExecutionState::Running;
                         pass = true;
                     } else if thread.check_grow() {
-                        thread.inner.state = ExecutionState::Running;
+                        if thread.inner.state == ExecutionState::Ready {
+                            thread.inner.state = ExecutionState::Running;
+                        }
                         pass = true;
                     } else {
                         unsafe { t.run_lock.unlock() };
@@ -605,7 +1008,11 @@ impl<T: Timer> UnfairScheduler<T> {
             unsafe { p.state_lock.unlock() };
             pass
         };
-        if let Some((p, t)) = &self.current {
+        // 当前进程已经退出的话可能已经从表中移除，不能再以它为起点找一圈
+        if let Some((p, t)) = &self.current
+            && p.inner.health == ProcessHealth::Healthy
+            && t.inner.state != ExecutionState::Dead
+        {
             table.move_next_thread_until(p, t, pred, false)
         } else {
             table.head_lock.lock();
@@ -628,11 +1035,11 @@ impl<T: Timer> UnfairScheduler<T> {
 
 impl<T: Timer> Scheduler for UnfairScheduler<T> {
     type Context = UnfairContext;
-    fn add(proc: Process, parent: Option<Pid>) -> Pid {
+    fn add(proc: Process, parent: Option<Pid>) -> Option<Pid> {
         let table = unsafe { &mut PROC_TABLE };
-        let pid = table.add(proc, parent);
+        let pid = table.add(proc, parent)?;
         hart::app::awake_idle();
-        pid
+        Some(pid)
     }
 
     fn find<F: FnMut(&mut Process)>(pid: Pid, mut action: F) -> bool {
@@ -688,7 +1095,7 @@ impl<T: Timer> Scheduler for UnfairScheduler<T> {
 
     fn schedule(&mut self) {
         // 采用 smooth 的代数算法，由于该算法存在进程间公平问题，干脆取消进程级别的公平比较，直接去保证线程公平，彻底放弃进程公平。
-        if let Some((_, t)) = &self.current {
+        if let Some((p, t)) = &self.current {
             let timeslice = if t.last_tick_time == 0 {
                 0
             } else {
@@ -698,24 +1105,40 @@ impl<T: Timer> Scheduler for UnfairScheduler<T> {
             thread.timeslice += timeslice;
             if t.inner.state == ExecutionState::Running {
                 thread.inner.state = ExecutionState::Ready;
+            } else if t.inner.state == ExecutionState::Dead
+                && p.inner.health == ProcessHealth::Healthy
+            {
+                // 释放 run_lock 之前回收，免得被 join 摘除后 tid 被复用时误回收新线程的栈
+                p.state_lock.lock();
+                p.get_mut().release_thread(t.id);
+                unsafe { p.state_lock.unlock() };
             }
             unsafe { t.run_lock.unlock() };
         }
+        self.wake_sleepers();
         let next = self.find_next();
         if let Some((_, t)) = &next {
             let remaining = QUANTUM - t.timeslice;
-            self.timer.schedule_next(remaining);
+            match self.next_alarm() {
+                Some(alarm) if alarm < remaining => self.timer.schedule_next(alarm),
+                _ => self.timer.schedule_next(remaining),
+            }
         }
         self.current = next;
     }
 
     fn cancel(&mut self) {
-        self.timer.put_off();
+        // 闲下来之后还有线程在本核心睡着，需要按时醒来叫醒它们
+        if let Some(alarm) = self.next_alarm() {
+            self.timer.schedule_next(alarm);
+        } else {
+            self.timer.put_off();
+        }
     }
 
     fn context(&self) -> Option<(Pid, Address, usize, Address)> {
         if let Some((p, t)) = &self.current {
-            let satp = p.inner.page_table_token();
+            let satp = p.inner.page_table_token(self.hartid);
             Some((p.id, p.layout.trampoline, satp, t.trapframe))
         } else {
             None
@@ -731,12 +1154,26 @@ impl<T: Timer> Scheduler for UnfairScheduler<T> {
                 process: p.clone(),
                 thread: t.clone(),
                 scheduled: false,
+                sleep: None,
+                alarm: None,
             };
             func(&mut context);
+            if let Some(sleeper) = context.sleep {
+                self.sleepers.push(sleeper);
+            }
+            if let Some(alarm) = context.alarm {
+                // 新的闹钟替换掉旧的
+                let mut alarms = ALARMS.lock();
+                alarms.retain(|(_, pid)| *pid != p.id);
+                if let Some(deadline) = alarm {
+                    alarms.push((deadline, p.id));
+                }
+            }
             if context.process.inner.signal.has_complete_uncleared() {
                 let mutable = context.process.get_mut();
-                let trapframe = mutable.struct_at::<TrapFrame>(t.trapframe);
-                mutable.inner.signal.restore(trapframe);
+                if let Some(trapframe) = mutable.struct_at::<TrapFrame>(t.trapframe) {
+                    mutable.inner.signal.restore(trapframe);
+                }
                 mutable.inner.signal.clear_complete();
             }
             schedule_request = context.scheduled;
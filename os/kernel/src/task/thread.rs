@@ -1,19 +1,27 @@
-use erhino_shared::{proc::ExecutionState, mem::Address};
+use erhino_shared::{
+    mem::Address,
+    proc::{ExecutionState, ExitCode},
+};
 
 use super::ipc::message::Mailbox;
 
 pub struct Thread {
     pub entry_point: Address,
+    pub argument: usize,
     pub state: ExecutionState,
-    pub mailbox: Mailbox
+    pub exit_code: ExitCode,
+    pub mailbox: Mailbox,
 }
 
 impl Thread {
-    pub fn new(entry: Address) -> Self {
+    pub fn new(entry: Address, argument: usize) -> Self {
         Self {
             entry_point: entry,
+            argument,
             state: ExecutionState::Ready,
-            mailbox: Mailbox::new()
+            exit_code: 0,
+            // 只用来暂存 Peek 出来等待 Receive 的那一条
+            mailbox: Mailbox::with_capacity(1, 1),
         }
     }
 }
@@ -1,7 +1,27 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use erhino_shared::time::{Timestamp, NS_PER_SEC};
+
 pub mod cpu;
 
+static TIMEBASE_FREQUENCY: AtomicUsize = AtomicUsize::new(0);
+
 pub trait Timer {
     fn uptime(&self) -> usize;
     fn schedule_next(&mut self, ms: usize);
     fn put_off(&mut self);
 }
+
+pub fn init(frequency: usize) {
+    // 时钟不走的话所有带期限的等待都不会到期
+    if frequency == 0 {
+        panic!("neither timebase-frequency nor clock-frequency is given by the device tree");
+    }
+    TIMEBASE_FREQUENCY.store(frequency, Ordering::Relaxed);
+}
+
+// 全局单调时钟，纳秒，由 mtime 和 timebase-frequency 换算，所有核心共享同一个 mtime
+pub fn monotonic() -> Timestamp {
+    let frequency = TIMEBASE_FREQUENCY.load(Ordering::Relaxed);
+    (cpu::time() as u128 * NS_PER_SEC as u128 / frequency as u128) as Timestamp
+}
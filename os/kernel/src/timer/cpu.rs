@@ -4,7 +4,7 @@ use super::Timer;
 
 const MS_PER_SEC: usize = 1000;
 
-fn time() -> usize {
+pub fn time() -> usize {
     // time refers to mtime, RustSBI will redirect and return the right value
     riscv::register::time::read()
 }
@@ -2,7 +2,7 @@ use core::fmt::Display;
 
 use erhino_shared::{
     call::{SystemCall, SystemCallError},
-    mem::{Address, MemoryOperation}, proc::Tid,
+    mem::{Address, MemoryOperation},
 };
 use num_traits::FromPrimitive;
 use riscv::register::{
@@ -24,6 +24,8 @@ pub struct SystemCallRequest<'context> {
     pub arg1: usize,
     pub arg2: usize,
     pub arg3: usize,
+    pub arg4: usize,
+    pub arg5: usize,
 }
 
 impl<'context> SystemCallRequest<'context> {
@@ -48,6 +50,10 @@ pub enum TrapCause {
     EnvironmentCall,
     Breakpoint,
     PageFault(Address, MemoryOperation),
+    AccessFault(Address, MemoryOperation),
+    Misaligned(Address, MemoryOperation),
+    // 非法指令的编码
+    IllegalInstruction(usize),
 }
 
 #[repr(C)]
@@ -75,11 +81,11 @@ impl TrapFrame {
         entry_point: Address,
         stack_address: Address,
         user_trap: Address,
-        thread_id: Tid,
+        thread_pointer: Address,
         registers: [u64; 2],
     ) {
         self.x = [0; 32];
-        self.x[4] = thread_id as u64;
+        self.x[4] = thread_pointer as u64;
         self.x[10] = registers[0];
         self.x[11] = registers[1];
         self.f = [0; 32];
@@ -98,6 +104,8 @@ impl TrapFrame {
             let arg1 = self.x[11] as usize;
             let arg2 = self.x[12] as usize;
             let arg3 = self.x[13] as usize;
+            let arg4 = self.x[14] as usize;
+            let arg5 = self.x[15] as usize;
             Some(SystemCallRequest {
                 trapframe: self,
                 call: call,
@@ -105,15 +113,34 @@ impl TrapFrame {
                 arg1,
                 arg2,
                 arg3,
+                arg4,
+                arg5,
             })
         } else {
             None
         }
     }
 
+    // 调用号都不认识的时候拿不到 SystemCallRequest，直接写错误
+    pub fn write_error(&mut self, error: SystemCallError) {
+        self.x[10] = error as u64;
+        self.x[11] = 0;
+    }
+
     pub fn move_next_instruction(&mut self) {
         self.pc += 4;
     }
+
+    // fork 出来的线程从同一个位置接着跑，内核那几项和 init 一样重新填
+    pub fn duplicate(&mut self, source: &TrapFrame) {
+        self.x = source.x;
+        self.f = source.f;
+        self.pc = source.pc;
+        self.kernel_tp = 0u64;
+        self.kernel_satp = unsafe { KERNEL_SATP } as u64;
+        self.kernel_trap = _kernel_trap as u64;
+        self.user_trap = source.user_trap;
+    }
 }
 
 impl Display for TrapFrame {
@@ -168,7 +195,29 @@ unsafe fn handle_user_trap(cause: Scause, val: usize) -> (usize, Address) {
                     val as Address,
                     MemoryOperation::Execute,
                 )),
-                _ => unimplemented!("unknown exception: {}:{:#x}", cause.bits(), val),
+                Exception::LoadFault => {
+                    hart.trap(TrapCause::AccessFault(val as Address, MemoryOperation::Read))
+                }
+                Exception::StoreFault => {
+                    hart.trap(TrapCause::AccessFault(val as Address, MemoryOperation::Write))
+                }
+                Exception::InstructionFault => hart.trap(TrapCause::AccessFault(
+                    val as Address,
+                    MemoryOperation::Execute,
+                )),
+                Exception::LoadMisaligned => {
+                    hart.trap(TrapCause::Misaligned(val as Address, MemoryOperation::Read))
+                }
+                Exception::StoreMisaligned => {
+                    hart.trap(TrapCause::Misaligned(val as Address, MemoryOperation::Write))
+                }
+                Exception::InstructionMisaligned => hart.trap(TrapCause::Misaligned(
+                    val as Address,
+                    MemoryOperation::Execute,
+                )),
+                Exception::IllegalInstruction => hart.trap(TrapCause::IllegalInstruction(val)),
+                // 用户态不该出现的异常，同样只结束肇事进程
+                _ => hart.trap(TrapCause::Unknown),
             },
             _ => {
                 unimplemented!("unknown trap from user: {}:{:#x}", cause.bits(), val)
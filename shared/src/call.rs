@@ -47,6 +47,8 @@ pub enum SystemCallError {
 pub enum SystemCall {
     // System reserved
     /// Makes kernel panic
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::All]
     Die = 0x0,
     /// Undefined behavior in release environment
     Debug = 0x01,
@@ -54,9 +56,27 @@ pub enum SystemCall {
     // -----Process control-----
     /// Finalized process notifies kernel to cleanup
     Exit = 0x10,
-    /// Spawn a process from the given bytes
+    /// Wait a child process to exit and collect its exit code
+    Wait = 0x11,
+    /// Drop permissions of the current process, only the given ones are kept
+    Restrict = 0x12,
+    /// Duplicate the current process, returns the pid of the child to the parent and 0 to the child.
+    /// The child also finds its own pid in `x12`. Pages are shared and copied when either side writes
+    ///
+    /// **Note**: Only the calling thread is duplicated and it becomes the main thread of the child,
+    /// tunnels are not inherited and the mailbox of the child is empty
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::Process], the child keeps all of the parent's
+    Fork = 0x13,
+    /// Spawn a process from the given bytes, the last argument points to a [crate::proc::StartupArguments] or is 0 for none
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::Process], the child gets a subset of the parent's,
+    /// or all of them with [crate::proc::PERMISSIONS_INHERITED]
     ExecuteBytes = 0x16,
-    /// Spawn a process from the file
+    /// Spawn a process from the file, the last argument points to a [crate::proc::StartupArguments] or is 0 for none
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::Process], the child gets a subset of the parent's,
+    /// or all of them with [crate::proc::PERMISSIONS_INHERITED]
     ExecuteFile = 0x17,
 
     // -----Thread-----
@@ -70,48 +90,83 @@ pub enum SystemCall {
     ThreadJoin = 0x23,
     /// Kill owned thread
     ThreadKill = 0x24,
+    /// Block the thread while the aligned u32 at the address equals the expected value,
+    /// until woken by [SystemCall::FutexWake] or the deadline passed, 0 for no deadline.
+    /// Returns 0 if timed out
+    ///
+    /// **Note**: Keyed by the virtual address, only threads of the same process can meet
+    FutexWait = 0x25,
+    /// Wake at most the given count of threads waiting on the address, returns how many were woken
+    FutexWake = 0x26,
 
     // -----Signal-----
     /// Return from signal handler
     SignalReturn = 0x30,
     /// Send a signal to the process
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::Process] if the target is not itself or a child
     SignalSend = 0x31,
-    /// Set signal handler for the current process
+    /// Set the handler of every signal in the mask for the current process, a null handler clears them.
+    /// Pending signals wait until they have a handler and are not blocked
     SignalSet = 0x32,
+    /// Block, unblock or replace the blocked signals, returns the previously blocked ones.
+    /// Blocked signals are queued and delivered after unblocked
+    SignalMask = 0x33,
+    /// Send [crate::proc::SystemSignal::Alarm] to the current process at the deadline, 0 cancels it.
+    /// Replaces the previous alarm, deadlines more than a day ahead are rejected
+    SignalAlarm = 0x34,
 
     // -----Messaging-----
-    /// Send a message carrying a huge payload then block until message received
+    /// Put a message carrying a payload into the mailbox of the target
+    ///
+    /// Full mailbox causes [SystemCallError::ObjectNotAvailable] and exceeded quota causes [SystemCallError::ReachLimit],
+    /// or blocks the sender until there is room with the blocking flag set
     Send = 0x40,
     /// Check the mailbox if there is a message and get the payload size
     /// 
     /// **Note**: Empty mailbox causes an expected error [SystemCallError::ObjectNotAvailable].
-    /// `Peek` will steal the content in the mailbox of the process and put it into the thread private space for `Receive` to use
+    /// `Peek` will steal the content in the mailbox of the process and put it into the thread private space for `Receive` to use.
+    /// With blocking flag set, the thread sleeps until a message arrives instead of getting the error
     Peek = 0x41,
-    /// Empty the mailbox
+    /// Drop the peeked message, or empty the whole mailbox as well with the flag set
     Discard = 0x42,
     /// Retrieve payload
     Receive = 0x43,
+    /// Set the capacity of the mailbox and the quota of slots each sender can hold
+    Reserve = 0x44,
+    /// Send a message then block until the receiver replies, the reply payload is copied into the given buffer
+    Call = 0x45,
+    /// Answer a message sent by `Call` with the reply token in its digest
+    Reply = 0x46,
     
     // -----Process memory-----
-    /// Map a range of virtual addresses for the process with kernel served pages
+    /// Grow the heap by the given bytes and return its new end, pages are served on first touch
     Extend = 0x50,
-    /// Map a range of virtual addresses for the process with specific range of physical addresses
-    /// 
-    /// **Permissions**: *Haven't determined yet*
+    /// Map zeroed pages between the heap and the stacks with the [crate::mem::MemoryRegionAttribute] bits,
+    /// at the page-aligned address or anywhere the kernel picks when it is 0. Returns the address
     Map = 0x51,
-    /// Tell kernel to reuse a range of virtual addresses
+    /// Unmap part of a range made by [SystemCall::Map] or [SystemCall::MapPhysical],
+    /// or give back the heap from the page-aligned address to its end. Returns the number of pages freed
     Free = 0x52,
+    /// Like [SystemCall::Map] but with the given physical pages outside of the memory, registers of devices for example
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::Memory]
+    MapPhysical = 0x53,
 
     // -----Tunnel-----
-    /// Allocate a key-marked random page
+    /// Allocate key-marked contiguous pages, the count must be a power of two.
+    /// Exceeding the total pages a process can own causes [SystemCallError::ReachLimit]
     TunnelBuild = 0x60,
-    /// Link a allocated page with a key
+    /// Link allocated pages with a key, the size in bytes is written back if asked
     TunnelLink = 0x61,
     /// Dispose the tunnel and restore the slot
     TunnelDispose = 0x62,
-    /// Interrupt for receiving
+    /// Allow a process to link the tunnel, only the builder and the granted process can link
+    TunnelGrant = 0x63,
+    /// Interrupt for receiving, wakes the peer or signals it with [crate::proc::SystemSignal::TunnelReady].
+    /// Blocks until the peer interrupts back with the flag set
     TunnelRequest = 0x6a,
-    /// Interrupt for transmitting
+    /// Interrupt for transmitting, the same as [SystemCall::TunnelRequest] in the other direction
     TunnelResponse = 0x6b,
 
     // -----Filesystem abstract layer-----
@@ -136,7 +191,17 @@ pub enum SystemCall {
     /// Write underlying bytes from buffer if is property with the same type
     Write = 0x79,
     /// Mount a filesystem service as a mount point at rootfs
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::Service]
     Mount = 0x7a,
     /// Unmount a mount point from rootfs
+    ///
+    /// **Permissions**: [crate::proc::ProcessPermission::Service]
     Unmount = 0x7b,
+
+    // -----Time-----
+    /// Read the monotonic clock in nanoseconds since the machine started
+    Clock = 0x80,
+    /// Block the thread until the monotonic clock reaches the deadline
+    Sleep = 0x81,
 }
@@ -19,6 +19,43 @@ flags! {
     }
 }
 
+/// Memory a process can take at most, a child never gets higher limits than its parent
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimit {
+    /// Pages mapped into the address space, shared ones included
+    pub page: usize,
+    /// Bytes of the heap extended by [crate::call::SystemCall::Extend]
+    pub heap: usize,
+    /// Bytes of the stacks of all threads
+    pub stack: usize,
+}
+
+impl MemoryLimit {
+    /// No limits at all
+    pub const UNLIMITED: Self = Self {
+        page: usize::MAX,
+        heap: usize::MAX,
+        stack: usize::MAX,
+    };
+    /// Take all the limits of the parent
+    pub const INHERITED: Self = Self {
+        page: 0,
+        heap: 0,
+        stack: 0,
+    };
+
+    /// Limits for a child, the lower one of each field. 0 in `requested` takes the one of `self`
+    pub fn inherit(&self, requested: &Self) -> Self {
+        let pick = |own: usize, asked: usize| if asked == 0 { own } else { own.min(asked) };
+        Self {
+            page: pick(self.page, requested.page),
+            heap: pick(self.heap, requested.heap),
+            stack: pick(self.stack, requested.stack),
+        }
+    }
+}
+
 /// Basic memory operation
 #[derive(Debug)]
 pub enum MemoryOperation {
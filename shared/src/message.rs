@@ -1,4 +1,7 @@
-use crate::{proc::Pid, time::Timestamp};
+use crate::{
+    proc::{Pid, Rid},
+    time::Timestamp,
+};
 
 /// Message sender and kind
 #[repr(C)]
@@ -7,15 +10,22 @@ pub struct MessageDigest {
     pub kind: usize,
     pub time: Timestamp,
     pub payload_length: usize,
+    /// Token to reply with if the sender is waiting for an answer, 0 for none
+    pub reply: Rid,
 }
 
 impl MessageDigest {
-    pub fn new(sender: Pid, kind: usize, time: Timestamp, length: usize) -> Self {
+    pub fn new(sender: Pid, kind: usize, time: Timestamp, length: usize, reply: Rid) -> Self {
         Self {
             sender,
             kind,
             time,
             payload_length: length,
+            reply,
         }
     }
+
+    pub fn expects_reply(&self) -> bool {
+        self.reply != 0
+    }
 }
@@ -1,6 +1,8 @@
 use flagset::flags;
 use num_derive::{FromPrimitive, ToPrimitive};
 
+use crate::mem::MemoryLimit;
+
 /// ExitCode(i64) type for process
 pub type ExitCode = i64;
 /// Pid(u32) type for process
@@ -13,6 +15,15 @@ pub type Rid = u64;
 /// SignalMap(u64) for process
 pub type SignalMap = u64;
 
+/// Exit code of a process finalized by [SystemSignal::Kill]
+pub const EXIT_CODE_KILLED: ExitCode = -9;
+/// Exit code of a process killed for an unhandled access to a bad address
+pub const EXIT_CODE_SEGMENTATION_FAULT: ExitCode = -11;
+/// Exit code of a process killed for an unhandled misaligned access
+pub const EXIT_CODE_MISALIGNED: ExitCode = -7;
+/// Exit code of a process killed for an unhandled illegal instruction
+pub const EXIT_CODE_ILLEGAL_INSTRUCTION: ExitCode = -4;
+
 flags! {
     /// Predefined signal numbers
     #[derive(FromPrimitive, ToPrimitive)]
@@ -23,6 +34,20 @@ flags! {
         Terminate = 1 << 0,
         /// Notify the process should check itself for (device interrupts, events listened)
         Notify = 1 << 1,
+        /// One of the child processes has exited and is waiting to be collected
+        ChildExit = 1 << 2,
+        /// The peer of a tunnel interrupted, the tunnel key comes as the argument
+        TunnelReady = 1 << 3,
+        /// Finalize the process at once with [EXIT_CODE_KILLED], can neither be caught nor blocked
+        Kill = 1 << 4,
+        /// The deadline set by [crate::call::SystemCall::SignalAlarm] has come
+        Alarm = 1 << 5,
+        /// Asked by the user to stop what the process is doing
+        Interrupt = 1 << 6,
+        /// The process made a fault, the faulting address (the instruction bits for an illegal instruction)
+        /// and pc come as the arguments.
+        /// Unhandled or blocked faults kill the process, so do faults in the handler of the main thread
+        Fault = 1 << 7,
     }
 }
 
@@ -47,6 +72,69 @@ flags! {
     }
 }
 
+/// How [crate::call::SystemCall::SignalMask] changes the blocked signals
+#[derive(Debug, FromPrimitive, ToPrimitive, Clone, Copy)]
+pub enum SignalMaskOperation {
+    /// Add the given signals to the blocked ones
+    Block = 0,
+    /// Remove the given signals from the blocked ones
+    Unblock = 1,
+    /// Replace the blocked signals with the given ones
+    Set = 2,
+}
+
+/// Permissions argument of [crate::call::SystemCall::ExecuteBytes] and [crate::call::SystemCall::ExecuteFile]
+/// giving the child all of the caller's, an empty set gives it none but [ProcessPermission::Valid]
+pub const PERMISSIONS_INHERITED: usize = usize::MAX;
+
+/// Bytes the arguments and environment variables of a process can take at most, tables included
+pub const STARTUP_LIMIT: usize = 0x10000;
+
+/// Keys of the auxiliary vector the kernel puts above the stack of the main thread
+///
+/// The main thread starts with the number of entries in a0 and the address of the vector in a1.
+/// Each entry is a pair of usize (key, value), the vector ends with [AuxiliaryKey::Null].
+#[repr(usize)]
+#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum AuxiliaryKey {
+    /// End of the vector
+    Null = 0,
+    /// Pid of the process
+    Pid = 1,
+    /// Pid of the parent, equals to the pid for processes started by the kernel
+    Parent = 2,
+    /// Size of a page in bytes
+    PageSize = 3,
+    /// Number of the arguments
+    ArgumentCount = 4,
+    /// Address of the argument table, an (address, length) pair of UTF-8 bytes for each argument
+    Arguments = 5,
+    /// Number of the environment variables
+    VariableCount = 6,
+    /// Address of the variable table laid out like the argument table, each one is `KEY=VALUE`
+    Variables = 7,
+}
+
+/// What [crate::call::SystemCall::ExecuteBytes] and [crate::call::SystemCall::ExecuteFile] pass to the child
+///
+/// Calling without it gives the child no arguments, no variables and all the limits of the parent
+///
+/// Both tables are arrays of (address, length) pairs of UTF-8 strings, variables are `KEY=VALUE`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct StartupArguments {
+    /// Address of the argument table
+    pub arguments: usize,
+    /// Number of the arguments
+    pub argument_count: usize,
+    /// Address of the variable table
+    pub variables: usize,
+    /// Number of the environment variables
+    pub variable_count: usize,
+    /// Memory limits of the child, see [MemoryLimit::inherit]
+    pub limit: MemoryLimit,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 /// States of process execution unit
 pub enum ExecutionState {
@@ -1 +1,5 @@
-pub type Timestamp = u64;
\ No newline at end of file
+/// Nanoseconds since the machine started
+pub type Timestamp = u64;
+
+pub const NS_PER_SEC: u64 = 1_000_000_000;
+pub const NS_PER_MS: u64 = 1_000_000;
@@ -3,9 +3,13 @@ use core::{arch::asm, mem::size_of};
 use erhino_shared::{
     call::{SystemCall, SystemCallError},
     fal::{DentryAttribute, DentryType},
-    mem::Address,
+    mem::{Address, MemoryRegionAttribute},
     message::MessageDigest,
-    proc::{ExitCode, Pid, SystemSignal, Tid},
+    proc::{
+        ExitCode, Pid, ProcessPermission, Rid, SignalMap, SignalMaskOperation, StartupArguments,
+        SystemSignal, Tid, PERMISSIONS_INHERITED,
+    },
+    time::Timestamp,
 };
 use flagset::FlagSet;
 use num_traits::FromPrimitive;
@@ -27,10 +31,12 @@ unsafe fn raw_call(
     arg1: usize,
     arg2: usize,
     arg3: usize,
+    arg4: usize,
+    arg5: usize,
 ) -> (usize, usize) {
     let mut error_code;
     let mut result;
-    asm!("ecall", in("x17") id, inlateout("x10") arg0 => error_code, inlateout("x11") arg1 => result, in("x12") arg2, in("x13") arg3);
+    asm!("ecall", in("x17") id, inlateout("x10") arg0 => error_code, inlateout("x11") arg1 => result, in("x12") arg2, in("x13") arg3, in("x14") arg4, in("x15") arg5);
     (error_code, result)
 }
 
@@ -41,7 +47,20 @@ unsafe fn sys_call(
     arg2: usize,
     arg3: usize,
 ) -> SystemCallResult<usize> {
-    let (error, ret) = raw_call(call as usize, arg0, arg1, arg2, arg3);
+    sys_call_extended(call, arg0, arg1, arg2, arg3, 0, 0)
+}
+
+// for the few calls taking more than 4 arguments
+unsafe fn sys_call_extended(
+    call: SystemCall,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+    arg5: usize,
+) -> SystemCallResult<usize> {
+    let (error, ret) = raw_call(call as usize, arg0, arg1, arg2, arg3, arg4, arg5);
     if error == 0 {
         Ok(ret)
     } else {
@@ -59,27 +78,192 @@ pub unsafe fn sys_extend(size: usize) -> SystemCallResult<Address> {
     sys_call(SystemCall::Extend, size, 0, 0, 0)
 }
 
+// returns the start of the mapped range, address 0 lets the kernel pick one
+pub unsafe fn sys_map(
+    address: Address,
+    length: usize,
+    attributes: FlagSet<MemoryRegionAttribute>,
+) -> SystemCallResult<Address> {
+    sys_call(SystemCall::Map, address, length, attributes.bits(), 0)
+}
+
+// returns the start of the mapped range, address 0 lets the kernel pick one
+pub unsafe fn sys_map_physical(
+    address: Address,
+    physical: Address,
+    length: usize,
+    attributes: FlagSet<MemoryRegionAttribute>,
+) -> SystemCallResult<Address> {
+    sys_call(
+        SystemCall::MapPhysical,
+        address,
+        physical,
+        length,
+        attributes.bits(),
+    )
+}
+
+// returns the number of pages freed
+pub unsafe fn sys_free(address: Address, length: usize) -> SystemCallResult<usize> {
+    sys_call(SystemCall::Free, address, length, 0, 0)
+}
+
 // returns nothing
 pub unsafe fn sys_exit(code: ExitCode) -> SystemCallResult<()> {
     sys_call(SystemCall::Exit, code as usize, 0, 0, 0).map(|_| ())
 }
 
-pub unsafe fn sys_thread_spawn(func_point: Address) -> SystemCallResult<Tid> {
-    sys_call(SystemCall::ThreadSpawn, func_point, 0, 0, 0).map(|t| t as Tid)
+// returns the permissions left
+pub unsafe fn sys_restrict(
+    kept: FlagSet<ProcessPermission>,
+) -> SystemCallResult<FlagSet<ProcessPermission>> {
+    sys_call(SystemCall::Restrict, kept.bits() as usize, 0, 0, 0)
+        .map(|p| FlagSet::<ProcessPermission>::new_truncated(p as u32))
+}
+
+// returns (the pid of the child, 0) in the parent and (0, its own pid) in the child
+pub unsafe fn sys_fork() -> SystemCallResult<(Pid, Pid)> {
+    let mut error_code: usize;
+    let mut result: usize;
+    let mut own: usize;
+    asm!("ecall", in("x17") SystemCall::Fork as usize, inlateout("x10") 0usize => error_code, inlateout("x11") 0usize => result, inlateout("x12") 0usize => own);
+    if error_code == 0 {
+        if result == 0 {
+            Ok((0, own as Pid))
+        } else {
+            Ok((result as Pid, 0))
+        }
+    } else {
+        Err(to_error(error_code))
+    }
+}
+
+fn permission_bits(permissions: Option<FlagSet<ProcessPermission>>) -> usize {
+    permissions.map_or(PERMISSIONS_INHERITED, |p| p.bits() as usize)
+}
+
+// returns the pid of the spawned process, None permissions means inheriting all of the caller's
+pub unsafe fn sys_execute_bytes(
+    bytes: &[u8],
+    permissions: Option<FlagSet<ProcessPermission>>,
+    startup: &StartupArguments,
+) -> SystemCallResult<Pid> {
+    sys_call(
+        SystemCall::ExecuteBytes,
+        bytes.as_ptr() as usize,
+        bytes.len(),
+        permission_bits(permissions),
+        startup as *const StartupArguments as usize,
+    )
+    .map(|p| p as Pid)
 }
 
-pub unsafe fn sys_tunnel_build() -> SystemCallResult<usize> {
-    sys_call(SystemCall::TunnelBuild, 0, 0, 0, 0)
+// returns the pid of the spawned process, None permissions means inheriting all of the caller's
+pub unsafe fn sys_execute_file(
+    path: &str,
+    permissions: Option<FlagSet<ProcessPermission>>,
+    startup: &StartupArguments,
+) -> SystemCallResult<Pid> {
+    sys_call(
+        SystemCall::ExecuteFile,
+        path.as_ptr() as usize,
+        path.len(),
+        permission_bits(permissions),
+        startup as *const StartupArguments as usize,
+    )
+    .map(|p| p as Pid)
+}
+
+// returns the pid of the collected child and writes its exit code, pid 0 for any child
+pub unsafe fn sys_wait(pid: Pid, code: &mut ExitCode) -> SystemCallResult<Pid> {
+    sys_call(
+        SystemCall::Wait,
+        pid as usize,
+        code as *mut ExitCode as usize,
+        0,
+        0,
+    )
+    .map(|p| p as Pid)
+}
+
+// the new thread starts with a0 = argument, a1 = its tid
+pub unsafe fn sys_thread_spawn(func_point: Address, argument: usize) -> SystemCallResult<Tid> {
+    sys_call(SystemCall::ThreadSpawn, func_point, argument, 0, 0).map(|t| t as Tid)
+}
+
+// returns nothing, exiting the main thread exits the process
+pub unsafe fn sys_thread_exit(code: ExitCode) -> SystemCallResult<()> {
+    sys_call(SystemCall::ThreadExit, code as usize, 0, 0, 0).map(|_| ())
+}
+
+pub unsafe fn sys_thread_yield() -> SystemCallResult<()> {
+    sys_call(SystemCall::ThreadYield, 0, 0, 0, 0).map(|_| ())
+}
+
+// blocks until the thread exits and writes its exit code
+pub unsafe fn sys_thread_join(tid: Tid, code: &mut ExitCode) -> SystemCallResult<()> {
+    sys_call(
+        SystemCall::ThreadJoin,
+        tid as usize,
+        code as *mut ExitCode as usize,
+        0,
+        0,
+    )
+    .map(|_| ())
 }
 
-pub unsafe fn sys_tunnel_link(key: usize) -> SystemCallResult<Address> {
-    sys_call(SystemCall::TunnelLink, key, 0, 0, 0)
+pub unsafe fn sys_thread_kill(tid: Tid) -> SystemCallResult<()> {
+    sys_call(SystemCall::ThreadKill, tid as usize, 0, 0, 0).map(|_| ())
+}
+
+// returns false if timed out, deadline 0 waits forever
+pub unsafe fn sys_futex_wait(
+    address: Address,
+    expected: u32,
+    deadline: Timestamp,
+) -> SystemCallResult<bool> {
+    sys_call(
+        SystemCall::FutexWait,
+        address,
+        expected as usize,
+        deadline as usize,
+        0,
+    )
+    .map(|w| w != 0)
+}
+
+// returns the count of threads woken
+pub unsafe fn sys_futex_wake(address: Address, count: usize) -> SystemCallResult<usize> {
+    sys_call(SystemCall::FutexWake, address, count, 0, 0)
+}
+
+pub unsafe fn sys_tunnel_build(pages: usize) -> SystemCallResult<usize> {
+    sys_call(SystemCall::TunnelBuild, pages, 0, 0, 0)
+}
+
+// size 中写入隧道的字节数
+pub unsafe fn sys_tunnel_link(key: usize, size: &mut usize) -> SystemCallResult<Address> {
+    sys_call(SystemCall::TunnelLink, key, size as *mut usize as usize, 0, 0)
 }
 
 pub unsafe fn sys_tunnel_dispose(key: usize) -> SystemCallResult<()> {
     sys_call(SystemCall::TunnelDispose, key, 0, 0, 0).map(|_| {})
 }
 
+pub unsafe fn sys_tunnel_grant(key: usize, pid: Pid) -> SystemCallResult<()> {
+    sys_call(SystemCall::TunnelGrant, key, pid as usize, 0, 0).map(|_| ())
+}
+
+// 通知对端需要数据，wait 时阻塞直到对端通知
+pub unsafe fn sys_tunnel_request(key: usize, wait: bool) -> SystemCallResult<()> {
+    sys_call(SystemCall::TunnelRequest, key, wait as usize, 0, 0).map(|_| ())
+}
+
+// 通知对端数据已就绪，wait 时阻塞直到对端通知
+pub unsafe fn sys_tunnel_response(key: usize, wait: bool) -> SystemCallResult<()> {
+    sys_call(SystemCall::TunnelResponse, key, wait as usize, 0, 0).map(|_| ())
+}
+
 pub unsafe fn sys_signal_set(
     mask: FlagSet<SystemSignal>,
     handler: Address,
@@ -100,6 +284,20 @@ pub unsafe fn sys_signal_send(pid: Pid, signal: SystemSignal) -> SystemCallResul
     .map(|f| f != 0)
 }
 
+// 返回之前屏蔽的信号
+pub unsafe fn sys_signal_mask(
+    operation: SignalMaskOperation,
+    mask: FlagSet<SystemSignal>,
+) -> SystemCallResult<SignalMap> {
+    sys_call(SystemCall::SignalMask, operation as usize, mask.bits() as usize, 0, 0)
+        .map(|m| m as SignalMap)
+}
+
+// deadline 为 0 时取消
+pub unsafe fn sys_signal_alarm(deadline: Timestamp) -> SystemCallResult<()> {
+    sys_call(SystemCall::SignalAlarm, deadline as usize, 0, 0, 0).map(|_| ())
+}
+
 pub unsafe fn sys_signal_return() -> SystemCallResult<()> {
     sys_call(SystemCall::SignalReturn, 0, 0, 0, 0).map(|_| ())
 }
@@ -157,28 +355,75 @@ pub unsafe fn sys_create(
     .map(|_| ())
 }
 
-pub unsafe fn sys_send(target: Pid, kind: usize, buffer: &[u8]) -> SystemCallResult<()> {
-    sys_call(
+// blocking send sleeps until the target mailbox has room for it
+pub unsafe fn sys_send(
+    target: Pid,
+    kind: usize,
+    buffer: &[u8],
+    blocking: bool,
+) -> SystemCallResult<()> {
+    sys_call_extended(
         SystemCall::Send,
         target as usize,
         kind,
         buffer.as_ptr() as usize,
         buffer.len(),
+        if blocking { 1 } else { 0 },
+        0,
+    )
+    .map(|_| ())
+}
+
+// returns the full length of the reply, which is truncated if the buffer is not large enough
+pub unsafe fn sys_call_reply(
+    target: Pid,
+    kind: usize,
+    buffer: &[u8],
+    reply_buffer: &mut [u8],
+) -> SystemCallResult<usize> {
+    sys_call_extended(
+        SystemCall::Call,
+        target as usize,
+        kind,
+        buffer.as_ptr() as usize,
+        buffer.len(),
+        reply_buffer.as_mut_ptr() as usize,
+        reply_buffer.len(),
+    )
+}
+
+pub unsafe fn sys_reply(token: Rid, buffer: &[u8]) -> SystemCallResult<()> {
+    sys_call(
+        SystemCall::Reply,
+        token as usize,
+        buffer.as_ptr() as usize,
+        buffer.len(),
+        0,
     )
     .map(|_| ())
 }
 
-pub unsafe fn sys_peek(digest_buffer: &[u8]) -> SystemCallResult<bool> {
+// blocking peek sleeps until a message arrives
+pub unsafe fn sys_peek(digest_buffer: &[u8], blocking: bool) -> SystemCallResult<bool> {
     sys_call(
         SystemCall::Peek,
         digest_buffer.as_ptr() as usize,
         size_of::<MessageDigest>(),
-        0,
+        if blocking { 1 } else { 0 },
         0,
     )
     .map(|b| b > 0)
 }
 
+// returns the count of messages dropped
+pub unsafe fn sys_discard(all: bool) -> SystemCallResult<usize> {
+    sys_call(SystemCall::Discard, if all { 1 } else { 0 }, 0, 0, 0)
+}
+
+pub unsafe fn sys_reserve(capacity: usize, quota: usize) -> SystemCallResult<()> {
+    sys_call(SystemCall::Reserve, capacity, quota, 0, 0).map(|_| ())
+}
+
 pub unsafe fn sys_receive(buffer: &[u8]) -> SystemCallResult<usize> {
     sys_call(
         SystemCall::Receive,
@@ -188,3 +433,13 @@ pub unsafe fn sys_receive(buffer: &[u8]) -> SystemCallResult<usize> {
         0,
     )
 }
+
+// nanoseconds since the machine started
+pub unsafe fn sys_clock() -> SystemCallResult<Timestamp> {
+    sys_call(SystemCall::Clock, 0, 0, 0, 0).map(|t| t as Timestamp)
+}
+
+// returns when the clock reaches the deadline
+pub unsafe fn sys_sleep(deadline: Timestamp) -> SystemCallResult<()> {
+    sys_call(SystemCall::Sleep, deadline as usize, 0, 0, 0).map(|_| ())
+}
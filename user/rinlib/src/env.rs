@@ -1,9 +1,64 @@
-use core::cell::OnceCell;
+use core::{
+    cell::OnceCell,
+    slice::{self, from_raw_parts},
+    str::from_utf8_unchecked,
+};
 
-use erhino_shared::proc::Pid;
+use erhino_shared::proc::{AuxiliaryKey, Pid};
+use num_traits::FromPrimitive;
 
 pub(crate) static mut PID: OnceCell<Pid> = OnceCell::new();
 pub(crate) static mut PARENT_PID: OnceCell<Pid> = OnceCell::new();
+static mut PAGE_SIZE: OnceCell<usize> = OnceCell::new();
+// 内核放在主线程栈顶上的 (地址, 长度) 表，进程活着它就一直在
+static mut ARGUMENTS: OnceCell<&'static [[usize; 2]]> = OnceCell::new();
+static mut VARIABLES: OnceCell<&'static [[usize; 2]]> = OnceCell::new();
+
+// 解析内核给主线程的辅助向量，只在 lang_start 里调用一次
+pub(crate) unsafe fn init(count: usize, vector: *const [usize; 2]) {
+    let mut page_size = 0x1000;
+    let (mut arguments, mut argument_count) = (0usize, 0usize);
+    let (mut variables, mut variable_count) = (0usize, 0usize);
+    for [key, value] in from_raw_parts(vector, count) {
+        match AuxiliaryKey::from_usize(*key) {
+            Some(AuxiliaryKey::Null) => break,
+            Some(AuxiliaryKey::Pid) => PID.set(*value as Pid).unwrap(),
+            Some(AuxiliaryKey::Parent) => PARENT_PID.set(*value as Pid).unwrap(),
+            Some(AuxiliaryKey::PageSize) => page_size = *value,
+            Some(AuxiliaryKey::ArgumentCount) => argument_count = *value,
+            Some(AuxiliaryKey::Arguments) => arguments = *value,
+            Some(AuxiliaryKey::VariableCount) => variable_count = *value,
+            Some(AuxiliaryKey::Variables) => variables = *value,
+            None => {}
+        }
+    }
+    PAGE_SIZE.set(page_size).unwrap();
+    ARGUMENTS.set(table(arguments, argument_count)).unwrap();
+    VARIABLES.set(table(variables, variable_count)).unwrap();
+}
+
+unsafe fn table(address: usize, count: usize) -> &'static [[usize; 2]] {
+    if address == 0 || count == 0 {
+        &[]
+    } else {
+        from_raw_parts(address as *const [usize; 2], count)
+    }
+}
+
+// 内核只收 UTF-8
+unsafe fn string(entry: &[usize; 2]) -> &'static str {
+    from_utf8_unchecked(from_raw_parts(entry[0] as *const u8, entry[1]))
+}
+
+// 子进程从 fork 返回时调用，原来的 pid 成了父进程的
+pub(crate) fn forked(own: Pid) {
+    unsafe {
+        let parent = PID.take().unwrap();
+        PID.set(own).unwrap();
+        PARENT_PID.take();
+        PARENT_PID.set(parent).unwrap();
+    }
+}
 
 pub fn pid() -> Pid {
     unsafe { *PID.get().unwrap() }
@@ -12,3 +67,71 @@ pub fn pid() -> Pid {
 pub fn parent_pid() -> Pid {
     unsafe { *PARENT_PID.get().unwrap() }
 }
+
+/// Size of a memory page in bytes
+pub fn page_size() -> usize {
+    unsafe { *PAGE_SIZE.get().unwrap() }
+}
+
+/// Iterator over the arguments of the process, see [args]
+pub struct Args {
+    inner: slice::Iter<'static, [usize; 2]>,
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|e| unsafe { string(e) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+impl DoubleEndedIterator for Args {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|e| unsafe { string(e) })
+    }
+}
+
+/// Iterator over the environment variables of the process, see [vars]
+pub struct Vars {
+    inner: slice::Iter<'static, [usize; 2]>,
+}
+
+impl Iterator for Vars {
+    type Item = (&'static str, &'static str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|e| unsafe { string(e) }.split_once('=').unwrap())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Arguments the process is spawned with, the first one is the path of the executable by convention
+pub fn args() -> Args {
+    Args {
+        inner: unsafe { ARGUMENTS.get().unwrap() }.iter(),
+    }
+}
+
+/// Environment variables the process is spawned with as (key, value) pairs
+pub fn vars() -> Vars {
+    Vars {
+        inner: unsafe { VARIABLES.get().unwrap() }.iter(),
+    }
+}
+
+/// Value of the environment variable, None if it is not present
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find(|(k, _)| *k == key).map(|(_, v)| v)
+}
@@ -2,21 +2,54 @@ use core::{mem::size_of, slice::from_raw_parts};
 
 use alloc::vec::Vec;
 use alloc::vec;
-use erhino_shared::{message::MessageDigest, proc::Pid};
+use erhino_shared::{call::SystemCallError, message::MessageDigest, proc::Pid};
 
-use crate::call::{sys_peek, sys_receive, sys_send};
+use crate::call::{
+    sys_call_reply, sys_discard, sys_peek, sys_receive, sys_reply, sys_reserve, sys_send,
+};
 
+#[derive(Debug)]
+pub enum CallError {
+    // Target does not exist
+    NotFound,
+    // Target mailbox is full or the quota is used up
+    Busy,
+    // Target exited or discarded the message without replying
+    Refused,
+    // Reply is larger than the buffer, which contains the truncated part, with the full length
+    Truncated(usize),
+    Unknown,
+}
+
+/// Fails if the mailbox of the target is full or the caller has used up its quota there
 pub fn send(target: Pid, kind: usize, payload: &[u8]) -> bool {
-    unsafe { sys_send(target, kind, payload) }.is_ok()
+    unsafe { sys_send(target, kind, payload, false) }.is_ok()
+}
+
+/// Wait for the target mailbox to have room instead of failing
+pub fn send_blocking(target: Pid, kind: usize, payload: &[u8]) -> bool {
+    unsafe { sys_send(target, kind, payload, true) }.is_ok()
 }
 
 pub fn peek() -> Option<MessageDigest> {
-    let digest = MessageDigest::new(0, 0, 0, 0);
+    peek_internal(false)
+}
+
+/// Block until a message arrives and peek it
+pub fn listen() -> Option<MessageDigest> {
+    peek_internal(true)
+}
+
+fn peek_internal(blocking: bool) -> Option<MessageDigest> {
+    let digest = MessageDigest::new(0, 0, 0, 0, 0);
     if let Ok(true) = unsafe {
-        sys_peek(from_raw_parts(
-            (&digest as *const MessageDigest) as *const u8,
-            size_of::<MessageDigest>(),
-        ))
+        sys_peek(
+            from_raw_parts(
+                (&digest as *const MessageDigest) as *const u8,
+                size_of::<MessageDigest>(),
+            ),
+            blocking,
+        )
     } {
         Some(digest)
     } else {
@@ -24,6 +57,46 @@ pub fn peek() -> Option<MessageDigest> {
     }
 }
 
+/// Send a message and wait for the target to reply, returns the length of the reply written into `reply_buffer`
+pub fn call(
+    target: Pid,
+    kind: usize,
+    payload: &[u8],
+    reply_buffer: &mut [u8],
+) -> Result<usize, CallError> {
+    let capacity = reply_buffer.len();
+    match unsafe { sys_call_reply(target, kind, payload, reply_buffer) } {
+        Ok(length) if length > capacity => Err(CallError::Truncated(length)),
+        Ok(length) => Ok(length),
+        Err(SystemCallError::ObjectNotFound) => Err(CallError::NotFound),
+        Err(SystemCallError::ObjectNotAccessible) => Err(CallError::Refused),
+        Err(SystemCallError::ObjectNotAvailable | SystemCallError::ReachLimit) => {
+            Err(CallError::Busy)
+        }
+        Err(_) => Err(CallError::Unknown),
+    }
+}
+
+/// Answer the message sent by [call], each message can be replied only once
+pub fn reply(handle: &MessageDigest, payload: &[u8]) -> bool {
+    handle.expects_reply() && unsafe { sys_reply(handle.reply, payload) }.is_ok()
+}
+
+/// Drop the peeked message without receiving its payload
+pub fn discard() -> bool {
+    matches!(unsafe { sys_discard(false) }, Ok(count) if count > 0)
+}
+
+/// Drop the peeked message and everything left in the mailbox, returns the count dropped
+pub fn discard_all() -> usize {
+    unsafe { sys_discard(true) }.unwrap_or(0)
+}
+
+/// Set how many messages the mailbox holds and how many of them a single sender can occupy
+pub fn reserve(capacity: usize, quota_per_sender: usize) -> bool {
+    unsafe { sys_reserve(capacity, quota_per_sender) }.is_ok()
+}
+
 pub fn receive(handle: &MessageDigest) -> Option<Vec<u8>> {
     let buffer = vec![0u8; handle.payload_length];
     if let Ok(_) = unsafe { sys_receive(&buffer) } {
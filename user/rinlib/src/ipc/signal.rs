@@ -1,40 +1,160 @@
+use core::mem::size_of;
+
 use erhino_shared::{
     call::SystemCallError,
-    proc::{Pid, SignalMap, SystemSignal},
+    mem::Address,
+    proc::{Pid, SignalMap, SignalMaskOperation, SystemSignal},
 };
 use flagset::FlagSet;
 use num_traits::FromPrimitive;
 
-use crate::call::{sys_signal_return, sys_signal_send, sys_signal_set};
+use crate::{
+    call::{
+        sys_signal_alarm, sys_signal_mask, sys_signal_return, sys_signal_send, sys_signal_set,
+    },
+    time::Instant,
+};
+
+const SIGNAL_COUNT: usize = size_of::<SignalMap>() * 8;
+
+// 内核只知道 signal_handler_wrapper，按信号位序号分发给各自的处理函数
+static mut SIGNAL_HANDLERS: [Option<fn(SystemSignal, SignalArgument)>; SIGNAL_COUNT] =
+    [None; SIGNAL_COUNT];
+
+/// The pair of words delivered along with a signal, meaning depends on the signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalArgument {
+    first: usize,
+    second: usize,
+}
+
+impl SignalArgument {
+    pub fn raw(&self) -> (usize, usize) {
+        (self.first, self.second)
+    }
+
+    /// The tunnel rung, for [SystemSignal::TunnelReady]
+    pub fn tunnel_key(&self) -> usize {
+        self.first
+    }
+
+    /// The address accessed, for [SystemSignal::Fault]. Zero for illegal instructions
+    pub fn fault_address(&self) -> Address {
+        self.first as Address
+    }
 
-static mut SIGNAL_HANDLER: Option<fn(SystemSignal)> = None;
+    /// The instruction faulted, for [SystemSignal::Fault]
+    pub fn fault_pc(&self) -> Address {
+        self.second as Address
+    }
+}
 
 #[derive(Debug)]
 pub enum SignalError {
     InternalError,
     ProcessNotFound,
+    PermissionDenied,
+    // Kill can not be caught
+    Uncatchable,
 }
 
-pub fn set_handler<S: Into<FlagSet<SystemSignal>>>(mask: S, handler: fn(SystemSignal)) {
+impl From<SystemCallError> for SignalError {
+    fn from(value: SystemCallError) -> Self {
+        match value {
+            SystemCallError::ObjectNotFound => SignalError::ProcessNotFound,
+            SystemCallError::PermissionDenied => SignalError::PermissionDenied,
+            SystemCallError::IllegalArgument => SignalError::Uncatchable,
+            _ => SignalError::InternalError,
+        }
+    }
+}
+
+/// The handler receives the signal and its argument, like the tunnel key of [SystemSignal::TunnelReady].
+/// Signals arrived before the handler is set are delivered once set.
+/// Returning from a [SystemSignal::Fault] handler retries the faulted instruction
+pub fn set_handler<S: Into<FlagSet<SystemSignal>>>(
+    signals: S,
+    handler: fn(SystemSignal, SignalArgument),
+) -> Result<(), SignalError> {
+    let signals = signals.into();
     unsafe {
-        SIGNAL_HANDLER = Some(handler);
-        sys_signal_set(mask.into(), signal_handler_wrapper as usize).expect("this wont failed");
+        sys_signal_set(signals, signal_handler_wrapper as usize)?;
+        for signal in signals.into_iter() {
+            SIGNAL_HANDLERS[(signal as SignalMap).trailing_zeros() as usize] = Some(handler);
+        }
     }
+    Ok(())
 }
 
-pub fn send(pid: Pid, signal: SystemSignal) -> Result<bool, SignalError> {
+/// Stop handling the signals, they stay pending until handled again
+pub fn clear_handler<S: Into<FlagSet<SystemSignal>>>(signals: S) -> Result<(), SignalError> {
+    let signals = signals.into();
     unsafe {
-        sys_signal_send(pid, signal).map_err(|e| match e {
-            SystemCallError::ObjectNotFound => SignalError::ProcessNotFound,
-            _ => SignalError::InternalError,
-        })
+        sys_signal_set(signals, 0)?;
+        for signal in signals.into_iter() {
+            SIGNAL_HANDLERS[(signal as SignalMap).trailing_zeros() as usize] = None;
+        }
     }
+    Ok(())
+}
+
+fn mask(
+    operation: SignalMaskOperation,
+    signals: FlagSet<SystemSignal>,
+) -> Result<FlagSet<SystemSignal>, SignalError> {
+    unsafe {
+        sys_signal_mask(operation, signals)
+            .map(FlagSet::new_truncated)
+            .map_err(|e| e.into())
+    }
+}
+
+/// Hold the signals back until unblocked, returns the previously blocked ones
+pub fn block<S: Into<FlagSet<SystemSignal>>>(
+    signals: S,
+) -> Result<FlagSet<SystemSignal>, SignalError> {
+    mask(SignalMaskOperation::Block, signals.into())
+}
+
+/// Deliver the held signals, returns the previously blocked ones
+pub fn unblock<S: Into<FlagSet<SystemSignal>>>(
+    signals: S,
+) -> Result<FlagSet<SystemSignal>, SignalError> {
+    mask(SignalMaskOperation::Unblock, signals.into())
+}
+
+/// Replace the blocked signals, returns the previously blocked ones
+pub fn set_blocked<S: Into<FlagSet<SystemSignal>>>(
+    signals: S,
+) -> Result<FlagSet<SystemSignal>, SignalError> {
+    mask(SignalMaskOperation::Set, signals.into())
+}
+
+/// Returns whether the target has a handler for it
+pub fn send(pid: Pid, signal: SystemSignal) -> Result<bool, SignalError> {
+    unsafe { sys_signal_send(pid, signal).map_err(|e| e.into()) }
+}
+
+/// Finalize the process without asking it
+pub fn kill(pid: Pid) -> Result<(), SignalError> {
+    send(pid, SystemSignal::Kill).map(|_| ())
+}
+
+/// Get [SystemSignal::Alarm] at the deadline, replacing the previous one
+pub fn alarm(deadline: Instant) -> Result<(), SignalError> {
+    unsafe { sys_signal_alarm(deadline.as_nanos()).map_err(|e| e.into()) }
+}
+
+pub fn cancel_alarm() -> Result<(), SignalError> {
+    unsafe { sys_signal_alarm(0).map_err(|e| e.into()) }
 }
 
-fn signal_handler_wrapper(signal: SignalMap) {
-    if let Some(handler) = unsafe { SIGNAL_HANDLER } {
-        if let Some(signal) = SystemSignal::from_u64(signal) {
-            handler(signal)
+fn signal_handler_wrapper(signal: SignalMap, first: usize, second: usize) {
+    if signal != 0 {
+        if let Some(handler) = unsafe { SIGNAL_HANDLERS[signal.trailing_zeros() as usize] } {
+            if let Some(signal) = SystemSignal::from_u64(signal) {
+                handler(signal, SignalArgument { first, second })
+            }
         }
     }
     unsafe {
@@ -1,7 +1,23 @@
-use erhino_shared::{call::SystemCallError, mem::Address};
+use core::{
+    cmp::min,
+    mem::size_of,
+    ptr,
+    slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::call::{sys_tunnel_build, sys_tunnel_dispose, sys_tunnel_link};
+use alloc::vec::Vec;
+use erhino_shared::{call::SystemCallError, mem::Address, proc::Pid};
 
+use crate::{
+    call::{
+        sys_tunnel_build, sys_tunnel_dispose, sys_tunnel_grant, sys_tunnel_link,
+        sys_tunnel_request, sys_tunnel_response,
+    },
+    thread::yield_now,
+};
+
+/// Size of a single tunnel page, tunnels are made of power-of-two pages
 pub const TUNNEL_FIELD_SIZE: usize = 4096;
 
 #[derive(Debug)]
@@ -9,7 +25,9 @@ pub enum TunnelError {
     Unknown,
     NotAccessible,
     IllegalAddress,
+    IllegalSize,
     OutOfMemory,
+    ReachLimit,
 }
 
 impl From<SystemCallError> for TunnelError {
@@ -21,6 +39,8 @@ impl From<SystemCallError> for TunnelError {
             SystemCallError::MemoryNotAccessible | SystemCallError::OutOfMemory => {
                 TunnelError::OutOfMemory
             }
+            SystemCallError::IllegalArgument => TunnelError::IllegalSize,
+            SystemCallError::ReachLimit => TunnelError::ReachLimit,
             _ => Self::Unknown,
         }
     }
@@ -29,14 +49,16 @@ impl From<SystemCallError> for TunnelError {
 pub struct Tunnel {
     key: usize,
     field: *mut u8,
+    size: usize,
 }
 
 impl Tunnel {
-    fn from_address(key: usize, addr: Address) -> Result<Self, TunnelError> {
+    fn from_address(key: usize, addr: Address, size: usize) -> Result<Self, TunnelError> {
         if addr & (TUNNEL_FIELD_SIZE - 1) == 0 {
             Ok(Self {
                 key,
                 field: addr as *mut u8,
+                size,
             })
         } else {
             Err(TunnelError::IllegalAddress)
@@ -47,6 +69,25 @@ impl Tunnel {
         self.key
     }
 
+    /// Size in bytes of the shared field
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The shared field, the peer may change it at any time
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.field, self.size) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.field, self.size) }
+    }
+
+    /// Let the process link the tunnel, pass the key to it afterwards, e.g. in a message
+    pub fn grant(&self, pid: Pid) -> Result<(), TunnelError> {
+        unsafe { sys_tunnel_grant(self.key, pid) }.map_err(|e| e.into())
+    }
+
     pub fn dispose(self) {
         // cleanup
         if let Ok(_) = unsafe { sys_tunnel_dispose(self.key) } {
@@ -55,29 +96,300 @@ impl Tunnel {
     }
 }
 
+/// Make a single page tunnel
 pub fn make() -> Result<Tunnel, TunnelError> {
-    match unsafe { sys_tunnel_build() } {
+    make_with_pages(1)
+}
+
+/// Make a tunnel of contiguous pages, the count must be a power of two
+pub fn make_with_pages(pages: usize) -> Result<Tunnel, TunnelError> {
+    if !pages.is_power_of_two() {
+        return Err(TunnelError::IllegalSize);
+    }
+    match unsafe { sys_tunnel_build(pages) } {
         Ok(id) => link(id),
         Err(err) => Err(err.into()),
     }
 }
 
 pub fn link(id: usize) -> Result<Tunnel, TunnelError> {
-    match unsafe { sys_tunnel_link(id) } {
-        Ok(addr) => Tunnel::from_address(id, addr),
+    let mut size = 0usize;
+    match unsafe { sys_tunnel_link(id, &mut size) } {
+        Ok(addr) => Tunnel::from_address(id, addr, size),
         Err(err) => Err(err.into()),
     }
 }
 
+/// Size of a single data block of a Runnel
+pub const RUNNEL_BLOCK_SIZE: usize = 1024;
+/// Number of data blocks in a Runnel field
+pub const RUNNEL_BLOCK_COUNT: usize = 3;
+
+// 端点状态，页帧分配时清零所以初始为 Vacant
+const ENDPOINT_VACANT: usize = 0;
+const ENDPOINT_OPEN: usize = 1;
+const ENDPOINT_CLOSED: usize = 2;
+const ENDPOINT_ABORTED: usize = 3;
+
+#[repr(C)]
+struct RunnelControl {
+    // 每块中有效数据的长度，0 表示空闲，只有发送端能把 0 改成非 0，只有接收端能改回 0
+    lengths: [AtomicUsize; RUNNEL_BLOCK_COUNT],
+    producer: AtomicUsize,
+    consumer: AtomicUsize,
+}
+
+#[repr(C)]
+struct RunnelField {
+    blocks: [[u8; RUNNEL_BLOCK_SIZE]; RUNNEL_BLOCK_COUNT],
+    control: RunnelControl,
+}
+
+const _: () = assert!(size_of::<RunnelField>() <= TUNNEL_FIELD_SIZE);
+
+#[derive(Debug)]
+pub enum RunnelError {
+    /// The role has been taken by another endpoint
+    Occupied,
+    /// Reading from the producer or writing to the consumer
+    WrongDirection,
+    /// The consumer has gone, nothing more can be written
+    Closed,
+    /// The peer gave up the transmission with error
+    Aborted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunnelRole {
+    Producer,
+    Consumer,
+}
+
+/// FIFO stream over a tunnel, one producer and one consumer
 pub struct Runnel {
     inner: Tunnel,
+    role: RunnelRole,
+    // 当前操作的块
+    cursor: usize,
+    // 接收端在当前块中已读的字节数
+    offset: usize,
 }
 
 impl Runnel {
+    fn new(inner: Tunnel, role: RunnelRole) -> Result<Self, RunnelError> {
+        let control = unsafe { &(*(inner.field as *const RunnelField)).control };
+        let this = match role {
+            RunnelRole::Producer => &control.producer,
+            RunnelRole::Consumer => &control.consumer,
+        };
+        if this
+            .compare_exchange(
+                ENDPOINT_VACANT,
+                ENDPOINT_OPEN,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            Ok(Self {
+                inner,
+                role,
+                cursor: 0,
+                offset: 0,
+            })
+        } else {
+            inner.dispose();
+            Err(RunnelError::Occupied)
+        }
+    }
+
+    /// Take the producer role of the tunnel
+    pub fn producer(inner: Tunnel) -> Result<Self, RunnelError> {
+        Self::new(inner, RunnelRole::Producer)
+    }
+
+    /// Take the consumer role of the tunnel
+    pub fn consumer(inner: Tunnel) -> Result<Self, RunnelError> {
+        Self::new(inner, RunnelRole::Consumer)
+    }
+
+    pub fn key(&self) -> usize {
+        self.inner.key()
+    }
+
+    pub fn role(&self) -> RunnelRole {
+        self.role
+    }
+
+    /// Let the process link as the peer
+    pub fn grant(&self, pid: Pid) -> Result<(), TunnelError> {
+        self.inner.grant(pid)
+    }
+
+    fn field(&self) -> &RunnelField {
+        unsafe { &*(self.inner.field as *const RunnelField) }
+    }
+
+    fn block(&self, index: usize) -> *mut u8 {
+        unsafe {
+            ptr::addr_of_mut!((*(self.inner.field as *mut RunnelField)).blocks[index]) as *mut u8
+        }
+    }
+
+    fn this(&self) -> &AtomicUsize {
+        let control = &self.field().control;
+        match self.role {
+            RunnelRole::Producer => &control.producer,
+            RunnelRole::Consumer => &control.consumer,
+        }
+    }
+
+    fn peer(&self) -> &AtomicUsize {
+        let control = &self.field().control;
+        match self.role {
+            RunnelRole::Producer => &control.consumer,
+            RunnelRole::Consumer => &control.producer,
+        }
+    }
+
+    // 通知对端，wait 时阻塞直到对端回应。对端没来得及关闭就离开了隧道视为出错
+    fn interrupt(&self, wait: bool) -> Result<(), RunnelError> {
+        let result = unsafe {
+            match self.role {
+                RunnelRole::Producer => sys_tunnel_response(self.inner.key, wait),
+                RunnelRole::Consumer => sys_tunnel_request(self.inner.key, wait),
+            }
+        };
+        match result {
+            Err(SystemCallError::ObjectNotAvailable) if wait => Err(RunnelError::Aborted),
+            // 内核不支持时退化成让出时间片
+            Err(_) if wait => {
+                yield_now();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Read some bytes, returns 0 once the producer has closed and everything is consumed
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, RunnelError> {
+        if self.role != RunnelRole::Consumer {
+            return Err(RunnelError::WrongDirection);
+        }
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let length = self.field().control.lengths[self.cursor].load(Ordering::Acquire);
+            if length != 0 {
+                let count = min(buffer.len(), length - self.offset);
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        self.block(self.cursor).add(self.offset),
+                        buffer.as_mut_ptr(),
+                        count,
+                    );
+                }
+                self.offset += count;
+                if self.offset == length {
+                    self.field().control.lengths[self.cursor].store(0, Ordering::Release);
+                    self.cursor = (self.cursor + 1) % RUNNEL_BLOCK_COUNT;
+                    self.offset = 0;
+                    let _ = self.interrupt(false);
+                }
+                return Ok(count);
+            }
+            match self.peer().load(Ordering::Acquire) {
+                ENDPOINT_ABORTED => return Err(RunnelError::Aborted),
+                ENDPOINT_CLOSED => {
+                    // 关闭前提交的块可能在两次检查之间才可见
+                    if self.field().control.lengths[self.cursor].load(Ordering::Acquire) == 0 {
+                        return Ok(0);
+                    }
+                }
+                _ => self.interrupt(true)?,
+            }
+        }
+    }
+
+    /// Read until the producer closes
+    pub fn read_to_end(&mut self, buffer: &mut Vec<u8>) -> Result<usize, RunnelError> {
+        let mut block = [0u8; RUNNEL_BLOCK_SIZE];
+        let mut total = 0usize;
+        loop {
+            match self.read(&mut block)? {
+                0 => return Ok(total),
+                count => {
+                    buffer.extend_from_slice(&block[..count]);
+                    total += count;
+                }
+            }
+        }
+    }
+
+    /// Write at most one block of bytes, blocks while all the blocks are in use
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, RunnelError> {
+        if self.role != RunnelRole::Producer {
+            return Err(RunnelError::WrongDirection);
+        }
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.peer().load(Ordering::Acquire) {
+                ENDPOINT_CLOSED => return Err(RunnelError::Closed),
+                ENDPOINT_ABORTED => return Err(RunnelError::Aborted),
+                _ => {}
+            }
+            let length = &self.field().control.lengths[self.cursor];
+            if length.load(Ordering::Acquire) == 0 {
+                let count = min(buffer.len(), RUNNEL_BLOCK_SIZE);
+                unsafe {
+                    ptr::copy_nonoverlapping(buffer.as_ptr(), self.block(self.cursor), count);
+                }
+                length.store(count, Ordering::Release);
+                self.cursor = (self.cursor + 1) % RUNNEL_BLOCK_COUNT;
+                let _ = self.interrupt(false);
+                return Ok(count);
+            }
+            self.interrupt(true)?;
+        }
+    }
+
+    pub fn write_all(&mut self, mut buffer: &[u8]) -> Result<(), RunnelError> {
+        while !buffer.is_empty() {
+            let count = self.write(buffer)?;
+            buffer = &buffer[count..];
+        }
+        Ok(())
+    }
+
+    fn shutdown(&self, state: usize) {
+        if self
+            .this()
+            .compare_exchange(ENDPOINT_OPEN, state, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            let _ = self.interrupt(false);
+        }
+    }
+
+    /// Tell the peer with end-of-stream, the data written is still readable
+    pub fn close(self) {
+        self.shutdown(ENDPOINT_CLOSED);
+    }
+
+    /// Tell the peer with error, the data unread is discarded
+    pub fn abort(self) {
+        self.shutdown(ENDPOINT_ABORTED);
+    }
 }
 
-impl From<Tunnel> for Runnel {
-    fn from(inner: Tunnel) -> Self {
-        Self { inner }
+impl Drop for Runnel {
+    fn drop(&mut self) {
+        self.shutdown(ENDPOINT_CLOSED);
+        unsafe {
+            let _ = sys_tunnel_dispose(self.inner.key);
+        }
     }
 }
@@ -1,4 +1,10 @@
-#![feature(lang_items, panic_info_message, alloc_error_handler)]
+#![feature(
+    lang_items,
+    panic_info_message,
+    alloc_error_handler,
+    thread_local,
+    allow_internal_unstable
+)]
 // Don't link to std. We are std.
 #![no_std]
 #![allow(internal_features)]
@@ -15,6 +21,8 @@ pub mod mm;
 pub mod preclude;
 mod rt;
 pub mod thread;
+pub mod time;
 pub mod process;
 pub mod fs;
-pub mod env;
\ No newline at end of file
+pub mod env;
+pub mod sync;
\ No newline at end of file
@@ -1 +1,81 @@
+use erhino_shared::{
+    call::SystemCallError,
+    mem::{Address, MemoryRegionAttribute},
+};
+use flagset::FlagSet;
 
+use crate::{
+    call::{sys_free, sys_map, sys_map_physical},
+    rt,
+};
+
+#[derive(Debug)]
+pub enum MemoryError {
+    Unknown,
+    // No memory left or the process reached its limits
+    OutOfMemory,
+    // Address is not page-aligned
+    InvalidAddress,
+    // Range overlaps others, is out of the mapping area, or is not owned by the process
+    NotAccessible,
+    // Zero length or attributes not allowed
+    InvalidArgument,
+    PermissionDenied,
+}
+
+impl From<SystemCallError> for MemoryError {
+    fn from(value: SystemCallError) -> Self {
+        match value {
+            SystemCallError::OutOfMemory => MemoryError::OutOfMemory,
+            SystemCallError::InvalidAddress => MemoryError::InvalidAddress,
+            SystemCallError::MemoryNotAccessible => MemoryError::NotAccessible,
+            SystemCallError::IllegalArgument => MemoryError::InvalidArgument,
+            SystemCallError::PermissionDenied => MemoryError::PermissionDenied,
+            _ => MemoryError::Unknown,
+        }
+    }
+}
+
+/// Map zeroed pages wherever the kernel finds room, returns the start of the range
+///
+/// The length is rounded up to pages. Writeable pages must also be readable
+pub fn map<A: Into<FlagSet<MemoryRegionAttribute>>>(
+    length: usize,
+    attributes: A,
+) -> Result<Address, MemoryError> {
+    map_at(0, length, attributes)
+}
+
+/// Map zeroed pages at the page-aligned address between the heap and the stacks
+pub fn map_at<A: Into<FlagSet<MemoryRegionAttribute>>>(
+    address: Address,
+    length: usize,
+    attributes: A,
+) -> Result<Address, MemoryError> {
+    unsafe { sys_map(address, length, attributes.into()).map_err(|e| e.into()) }
+}
+
+/// Map physical pages outside of the memory, used by drivers to reach device registers
+///
+/// Address 0 lets the kernel pick where to put them. Needs [ProcessPermission::Memory](erhino_shared::proc::ProcessPermission::Memory)
+pub fn map_physical<A: Into<FlagSet<MemoryRegionAttribute>>>(
+    address: Address,
+    physical: Address,
+    length: usize,
+    attributes: A,
+) -> Result<Address, MemoryError> {
+    unsafe { sys_map_physical(address, physical, length, attributes.into()).map_err(|e| e.into()) }
+}
+
+/// Unmap part of a mapped range, returns the number of pages given back
+///
+/// # Safety
+/// Nothing may refer to the range any longer
+pub unsafe fn free(address: Address, length: usize) -> Result<usize, MemoryError> {
+    sys_free(address, length).map_err(|e| e.into())
+}
+
+/// Give the unused pages at the end of the heap back to the kernel, returns the number of pages freed
+pub fn trim() -> usize {
+    rt::trim_heap()
+}
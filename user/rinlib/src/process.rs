@@ -0,0 +1,280 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use erhino_shared::{
+    call::SystemCallError,
+    mem::MemoryLimit,
+    proc::{ExitCode, Pid, ProcessPermission, StartupArguments},
+};
+use flagset::FlagSet;
+
+use crate::{
+    call::{sys_execute_bytes, sys_execute_file, sys_fork, sys_restrict, sys_wait},
+    env,
+};
+
+#[derive(Debug)]
+pub enum ProcessSpawnError {
+    Unknown,
+    // Executable file does not exist
+    NotFound,
+    // File is not an executable for this machine
+    InvalidBinary,
+    PermissionDenied,
+    OutOfMemory,
+    // Arguments are too long or a variable key is empty or contains '='
+    InvalidArgument,
+}
+
+impl From<SystemCallError> for ProcessSpawnError {
+    fn from(value: SystemCallError) -> Self {
+        match value {
+            SystemCallError::ObjectNotFound => ProcessSpawnError::NotFound,
+            SystemCallError::IllegalArgument | SystemCallError::NotSupported => {
+                ProcessSpawnError::InvalidBinary
+            }
+            SystemCallError::PermissionDenied | SystemCallError::ObjectNotAccessible => {
+                ProcessSpawnError::PermissionDenied
+            }
+            SystemCallError::OutOfMemory | SystemCallError::MemoryNotAccessible => {
+                ProcessSpawnError::OutOfMemory
+            }
+            _ => ProcessSpawnError::Unknown,
+        }
+    }
+}
+
+pub struct Process {
+    pid: Pid,
+}
+
+impl Process {
+    fn new(pid: Pid) -> Self {
+        Self { pid }
+    }
+
+    pub fn id(&self) -> Pid {
+        self.pid
+    }
+
+    /// Block until the process exits and collect its exit code
+    ///
+    /// Returns None if it is not a child of the caller or has already been collected
+    pub fn wait(self) -> Option<ExitCode> {
+        let mut code: ExitCode = 0;
+        match unsafe { sys_wait(self.pid, &mut code) } {
+            Ok(_) => Some(code),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Block until any of the child processes exits and collect its pid and exit code
+///
+/// Returns None if the caller has no child to wait for
+pub fn wait_any() -> Option<(Pid, ExitCode)> {
+    let mut code: ExitCode = 0;
+    match unsafe { sys_wait(0, &mut code) } {
+        Ok(pid) => Some((pid, code)),
+        Err(_) => None,
+    }
+}
+
+/// Builder of a process spawned with arguments and environment variables
+pub struct Command {
+    path: String,
+    arguments: Vec<String>,
+    variables: Vec<(String, String)>,
+    // None 为原样继承调用者的
+    permissions: Option<FlagSet<ProcessPermission>>,
+    limit: MemoryLimit,
+}
+
+impl Command {
+    /// The path becomes the first argument, the environment variables are inherited from the caller
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            arguments: Vec::from([path.to_string()]),
+            variables: env::vars()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            permissions: None,
+            limit: MemoryLimit::INHERITED,
+        }
+    }
+
+    pub fn arg(&mut self, argument: &str) -> &mut Self {
+        self.arguments.push(argument.to_string());
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: AsRef<str>>(&mut self, arguments: I) -> &mut Self {
+        for argument in arguments {
+            self.arguments.push(argument.as_ref().to_string());
+        }
+        self
+    }
+
+    /// Add or replace an environment variable
+    pub fn env(&mut self, key: &str, value: &str) -> &mut Self {
+        self.env_remove(key);
+        self.variables.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn env_remove(&mut self, key: &str) -> &mut Self {
+        self.variables.retain(|(k, _)| k != key);
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.variables.clear();
+        self
+    }
+
+    /// Give the child a subset of the caller's permissions instead of all of them, an empty set gives it none
+    pub fn permissions<P: Into<FlagSet<ProcessPermission>>>(
+        &mut self,
+        permissions: P,
+    ) -> &mut Self {
+        self.permissions = Some(permissions.into());
+        self
+    }
+
+    /// Lower the memory limits of the child, it can never get more than the caller has
+    pub fn limit(&mut self, limit: MemoryLimit) -> &mut Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn spawn(&self) -> Result<Process, ProcessSpawnError> {
+        let startup = Startup::new(&self.arguments, &self.variables)?;
+        let arguments = startup.arguments(self.limit);
+        match unsafe { sys_execute_file(&self.path, self.permissions, &arguments) } {
+            Ok(pid) => Ok(Process::new(pid)),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// 参数和 KEY=VALUE 的 (地址, 长度) 表，系统调用返回之前字符串都得活着
+struct Startup {
+    variables: Vec<String>,
+    argument_table: Vec<[usize; 2]>,
+    variable_table: Vec<[usize; 2]>,
+}
+
+impl Startup {
+    fn new(
+        arguments: &[String],
+        variables: &[(String, String)],
+    ) -> Result<Self, ProcessSpawnError> {
+        if variables
+            .iter()
+            .any(|(k, _)| k.is_empty() || k.contains('='))
+        {
+            return Err(ProcessSpawnError::InvalidArgument);
+        }
+        let variables: Vec<String> = variables
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        let argument_table = arguments
+            .iter()
+            .map(|a| [a.as_ptr() as usize, a.len()])
+            .collect();
+        let variable_table = variables
+            .iter()
+            .map(|v| [v.as_ptr() as usize, v.len()])
+            .collect();
+        Ok(Self {
+            variables,
+            argument_table,
+            variable_table,
+        })
+    }
+
+    fn arguments(&self, limit: MemoryLimit) -> StartupArguments {
+        StartupArguments {
+            arguments: self.argument_table.as_ptr() as usize,
+            argument_count: self.argument_table.len(),
+            variables: self.variable_table.as_ptr() as usize,
+            variable_count: self.variable_table.len(),
+            limit,
+        }
+    }
+}
+
+/// Spawn a process from the executable file, the caller becomes its parent and shares all its permissions
+pub fn spawn(path: &str) -> Result<Process, ProcessSpawnError> {
+    Command::new(path).spawn()
+}
+
+/// Spawn a process from the executable file with a subset of the caller's permissions
+pub fn spawn_with_permissions<P: Into<FlagSet<ProcessPermission>>>(
+    path: &str,
+    permissions: P,
+) -> Result<Process, ProcessSpawnError> {
+    Command::new(path).permissions(permissions).spawn()
+}
+
+/// Spawn a process from an elf image in memory, the caller becomes its parent and shares all its permissions
+pub fn spawn_from_bytes(bytes: &[u8]) -> Result<Process, ProcessSpawnError> {
+    spawn_from_bytes_internal(bytes, None)
+}
+
+/// Spawn a process from an elf image in memory with a subset of the caller's permissions
+///
+/// The child gets no arguments and inherits the environment variables of the caller
+pub fn spawn_from_bytes_with_permissions<P: Into<FlagSet<ProcessPermission>>>(
+    bytes: &[u8],
+    permissions: P,
+) -> Result<Process, ProcessSpawnError> {
+    spawn_from_bytes_internal(bytes, Some(permissions.into()))
+}
+
+fn spawn_from_bytes_internal(
+    bytes: &[u8],
+    permissions: Option<FlagSet<ProcessPermission>>,
+) -> Result<Process, ProcessSpawnError> {
+    let variables: Vec<(String, String)> = env::vars()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let startup = Startup::new(&[], &variables)?;
+    let arguments = startup.arguments(MemoryLimit::INHERITED);
+    match unsafe { sys_execute_bytes(bytes, permissions, &arguments) } {
+        Ok(pid) => Ok(Process::new(pid)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Which side of [fork] the caller is on
+pub enum Fork {
+    Parent(Process),
+    Child,
+}
+
+/// Duplicate the calling process, memory is shared and copied on write
+///
+/// Only the calling thread goes on in the child. Locks held by other threads at the moment stay locked there,
+/// so the child should avoid the heap if the parent allocates on several threads
+pub fn fork() -> Result<Fork, ProcessSpawnError> {
+    match unsafe { sys_fork() } {
+        Ok((0, own)) => {
+            env::forked(own);
+            Ok(Fork::Child)
+        }
+        Ok((pid, _)) => Ok(Fork::Parent(Process::new(pid))),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Drop the permissions not in `kept` for the rest of the process's life, returns what is left
+///
+/// Dropped permissions can never be gained back, nor be given to children spawned later
+pub fn restrict<P: Into<FlagSet<ProcessPermission>>>(kept: P) -> FlagSet<ProcessPermission> {
+    unsafe { sys_restrict(kept.into()).expect("restricting permissions never fails") }
+}
@@ -1,11 +1,18 @@
 use core::{alloc::Layout, panic::PanicInfo};
-use erhino_shared::proc::{Pid, SystemSignal, Termination};
-use erhino_shared::sync::spin::SimpleLock;
+use erhino_shared::{
+    mem::MemoryRegionAttribute,
+    proc::{SystemSignal, Termination},
+};
 use talc::{OomHandler, Span, Talc, Talck};
 
-use crate::call::sys_extend;
+use crate::call::{sys_extend, sys_free, sys_map};
 use crate::env;
-use crate::{call::sys_exit, debug, ipc::signal};
+use crate::sync::RawMutex;
+use crate::{
+    call::sys_exit,
+    debug,
+    ipc::signal::{self, SignalArgument},
+};
 
 const INITIAL_HEAP_SIZE: usize = 1 * 0x1000;
 
@@ -25,29 +32,69 @@ impl OomHandler for HeapRecuse {
     fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()> {
         let mut count = 1;
         let single = 4096;
-        while count * single < layout.size() {
+        // 留出 talc 自己的元数据
+        while count * single < layout.size() + layout.align() + single {
             count *= 2;
         }
+        let size = count * single;
+        let attributes = MemoryRegionAttribute::Read | MemoryRegionAttribute::Write;
         let old = talc.oom_handler.heap;
-        if let Ok(offset) = unsafe { sys_extend(count) } {
-            let size = count * single;
-            let new = if old.is_empty() {
-                Span::new((offset - size) as *mut u8, offset as *mut u8)
-            } else {
-                old.extend(0, size)
+        // Extend 要的是字节数，返回新的堆顶
+        if let Ok(offset) = unsafe { sys_extend(size) } {
+            let new = match old.get_base_acme() {
+                Some((base, _)) => Span::new(base, offset as *mut u8),
+                None => Span::new((offset - size) as *mut u8, offset as *mut u8),
             };
             unsafe {
                 talc.oom_handler.heap = talc.extend(old, new);
             }
             Ok(())
+        } else if let Ok(address) = unsafe { sys_map(0, size, attributes) } {
+            // 堆长不动了（碰到 Map 的区域或者到了上限）就另外映射一块，这块不会被 trim 归还
+            unsafe {
+                talc.claim(Span::from_base_size(address as *mut u8, size))
+                    .map(|_| ())
+            }
         } else {
             Err(())
         }
     }
 }
 
+// 把堆尾没有分配出去的整页还给内核，堆至少留下初始的大小
+pub(crate) fn trim_heap() -> usize {
+    let single = 4096;
+    let mut talc = unsafe { HEAP_ALLOCATOR.talc() };
+    let heap = talc.oom_handler.heap;
+    let (base, acme) = match heap.get_base_acme() {
+        Some(pair) => pair,
+        None => return 0,
+    };
+    let used = match talc.get_allocated_span(heap).get_base_acme() {
+        Some((_, top)) => top as usize,
+        None => base as usize,
+    };
+    let keep = ((used + single - 1) & !(single - 1)).max(base as usize + INITIAL_HEAP_SIZE);
+    if keep >= acme as usize {
+        return 0;
+    }
+    let new = unsafe { talc.truncate(heap, Span::new(base, keep as *mut u8)) };
+    talc.oom_handler.heap = new;
+    // talc 可能多留一点，只还它不再用的整页
+    let start = match new.get_base_acme() {
+        Some((_, top)) => (top as usize + single - 1) & !(single - 1),
+        None => keep,
+    };
+    if start < acme as usize {
+        unsafe { sys_free(start, acme as usize - start).unwrap_or(0) }
+    } else {
+        0
+    }
+}
+
+// 多线程抢堆的时候睡在内核里，不空转
 #[global_allocator]
-static mut HEAP_ALLOCATOR: Talck<SimpleLock, HeapRecuse> = Talc::new(HeapRecuse::new()).lock();
+static mut HEAP_ALLOCATOR: Talck<RawMutex, HeapRecuse> = Talc::new(HeapRecuse::new()).lock();
 
 #[lang = "start"]
 fn lang_start<T: Termination + 'static>(
@@ -56,11 +103,9 @@ fn lang_start<T: Termination + 'static>(
     argv: *const *const u8,
     _sigpipe: u8,
 ) -> isize {
-    let pid = argc as usize as Pid;
-    let parent = argv as usize as Pid;
     unsafe {
-        env::PID.set(pid).unwrap();
-        env::PARENT_PID.set(parent).unwrap();
+        // argc 和 argv 实际上是辅助向量的条目数和地址
+        env::init(argc as usize, argv as *const [usize; 2]);
         let mut talc = HEAP_ALLOCATOR.talc();
         if let Ok(offset) = sys_extend(INITIAL_HEAP_SIZE) {
             let start = offset - INITIAL_HEAP_SIZE;
@@ -74,7 +119,7 @@ fn lang_start<T: Termination + 'static>(
             panic!();
         }
     }
-    signal::set_handler(SystemSignal::Terminate, default_signal_handler);
+    signal::set_handler(SystemSignal::Terminate, default_signal_handler).expect("this wont failed");
     let code = main().to_exit_code();
     unsafe {
         loop {
@@ -107,7 +152,7 @@ fn handle_alloc_error(layout: Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
-fn default_signal_handler(signal: SystemSignal) {
+fn default_signal_handler(signal: SystemSignal, _argument: SignalArgument) {
     match signal {
         SystemSignal::Terminate => unsafe {
             sys_exit(1).expect("no wish to die");
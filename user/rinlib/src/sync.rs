@@ -0,0 +1,12 @@
+mod condvar;
+mod futex;
+mod mutex;
+mod once;
+mod rwlock;
+mod semaphore;
+
+pub use condvar::Condvar;
+pub use mutex::{Mutex, MutexGuard, RawMutex};
+pub use once::Once;
+pub use rwlock::{RawRwLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::Semaphore;
@@ -0,0 +1,66 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::time::{Duration, Instant};
+
+use super::{futex, MutexGuard};
+
+/// Block threads until some condition holds, always used with a [super::Mutex]
+pub struct Condvar {
+    // 每次通知都加一，等待方拿着锁读到的值变了就说明错过的通知已经发生
+    sequence: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            sequence: AtomicU32::new(0),
+        }
+    }
+
+    /// Release the lock and block until notified, the lock is held again when returned.
+    /// Spurious wakeups are possible, check the condition in a loop or use [Condvar::wait_while]
+    pub fn wait<'a, T>(&self, mut guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        MutexGuard::unlocked(&mut guard, || {
+            futex::wait(&self.sequence, sequence, None);
+        });
+        guard
+    }
+
+    /// Block until the condition returns false
+    pub fn wait_while<'a, T, F: FnMut(&mut T) -> bool>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// The same as [Condvar::wait] but gives up after the duration, returns true if timed out
+    pub fn wait_timeout<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        duration: Duration,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let deadline = Instant::now() + duration;
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        let mut woken = true;
+        MutexGuard::unlocked(&mut guard, || {
+            woken = futex::wait(&self.sequence, sequence, Some(deadline));
+        });
+        (guard, !woken)
+    }
+
+    pub fn notify_one(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex::wake_one(&self.sequence);
+    }
+
+    pub fn notify_all(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex::wake_all(&self.sequence);
+    }
+}
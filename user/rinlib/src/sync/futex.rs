@@ -0,0 +1,28 @@
+use core::sync::atomic::AtomicU32;
+
+use erhino_shared::mem::Address;
+
+use crate::{
+    call::{sys_futex_wait, sys_futex_wake},
+    time::Instant,
+};
+
+// 值还是 expected 的时候挂起，返回 false 表示超时。被叫醒不代表条件满足，调用方要自己再检查
+pub fn wait(futex: &AtomicU32, expected: u32, deadline: Option<Instant>) -> bool {
+    let deadline = deadline.map_or(0, |d| d.as_nanos());
+    // 出错只可能是地址不对，当作醒了让调用方重新检查
+    unsafe { sys_futex_wait(futex as *const AtomicU32 as Address, expected, deadline) }
+        .unwrap_or(true)
+}
+
+pub fn wake_one(futex: &AtomicU32) -> bool {
+    wake(futex, 1) > 0
+}
+
+pub fn wake_all(futex: &AtomicU32) -> usize {
+    wake(futex, usize::MAX)
+}
+
+fn wake(futex: &AtomicU32, count: usize) -> usize {
+    unsafe { sys_futex_wake(futex as *const AtomicU32 as Address, count) }.unwrap_or(0)
+}
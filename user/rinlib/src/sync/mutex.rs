@@ -0,0 +1,62 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use lock_api::GuardSend;
+
+use super::futex;
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+// 有人在等，解锁时要叫醒一个
+const CONTENDED: u32 = 2;
+
+/// A lock parking the contending threads in the kernel instead of spinning
+pub struct RawMutex {
+    state: AtomicU32,
+}
+
+impl RawMutex {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+        }
+    }
+
+    #[cold]
+    fn lock_contended(&self) {
+        // 先标记为 CONTENDED 再睡，持有者解锁时就知道要叫人
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            futex::wait(&self.state, CONTENDED, None);
+        }
+    }
+}
+
+unsafe impl lock_api::RawMutex for RawMutex {
+    const INIT: Self = RawMutex::new();
+
+    type GuardMarker = GuardSend;
+
+    fn lock(&self) {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex::wake_one(&self.state);
+        }
+    }
+}
+
+pub type Mutex<T> = lock_api::Mutex<RawMutex, T>;
+pub type MutexGuard<'a, T> = lock_api::MutexGuard<'a, RawMutex, T>;
@@ -0,0 +1,53 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::futex;
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const COMPLETE: u32 = 2;
+
+/// Run a global initialization exactly once
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    /// Run the closure if no one has done it, the others calling at the same time block until it's done
+    pub fn call_once<F: FnOnce()>(&self, func: F) {
+        if self.is_completed() {
+            return;
+        }
+        let mut func = Some(func);
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    if let Some(func) = func.take() {
+                        func();
+                    }
+                    self.state.store(COMPLETE, Ordering::Release);
+                    futex::wake_all(&self.state);
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(_) => {
+                    futex::wait(&self.state, RUNNING, None);
+                }
+            }
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
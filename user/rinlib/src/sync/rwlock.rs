@@ -0,0 +1,96 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use lock_api::GuardSend;
+
+use super::futex;
+
+// state 为读者数量，写者持有时为 WRITE_LOCKED
+const WRITE_LOCKED: u32 = u32::MAX;
+
+/// A reader-writer lock parking the blocked threads in the kernel
+pub struct RawRwLock {
+    state: AtomicU32,
+    // 没人等的时候省掉唤醒的系统调用
+    waiters: AtomicU32,
+}
+
+impl RawRwLock {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    // 登记等待者之后值仍然是 state 才睡，释放方改完值再看有没有人等，两边都用 SeqCst 保证不会错过
+    fn wait(&self, state: u32) {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        if self.state.load(Ordering::SeqCst) == state {
+            futex::wait(&self.state, state, None);
+        }
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn wake(&self) {
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            futex::wake_all(&self.state);
+        }
+    }
+}
+
+unsafe impl lock_api::RawRwLock for RawRwLock {
+    const INIT: Self = RawRwLock::new();
+
+    type GuardMarker = GuardSend;
+
+    fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            let state = self.state.load(Ordering::Relaxed);
+            if state == WRITE_LOCKED {
+                self.wait(state);
+            }
+        }
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        self.state
+            .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |s| {
+                if s < WRITE_LOCKED - 1 {
+                    Some(s + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    unsafe fn unlock_shared(&self) {
+        if self.state.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.wake();
+        }
+    }
+
+    fn lock_exclusive(&self) {
+        while let Err(state) =
+            self.state
+                .compare_exchange(0, WRITE_LOCKED, Ordering::SeqCst, Ordering::Relaxed)
+        {
+            self.wait(state);
+        }
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITE_LOCKED, Ordering::SeqCst, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.state.store(0, Ordering::SeqCst);
+        self.wake();
+    }
+}
+
+pub type RwLock<T> = lock_api::RwLock<RawRwLock, T>;
+pub type RwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, RawRwLock, T>;
+pub type RwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, RawRwLock, T>;
@@ -0,0 +1,56 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::time::{Duration, Instant};
+
+use super::futex;
+
+/// Counting semaphore, acquiring with no permit left blocks the thread
+pub struct Semaphore {
+    permits: AtomicU32,
+}
+
+impl Semaphore {
+    pub const fn new(permits: u32) -> Self {
+        Self {
+            permits: AtomicU32::new(permits),
+        }
+    }
+
+    pub fn acquire(&self) {
+        while !self.try_acquire() {
+            futex::wait(&self.permits, 0, None);
+        }
+    }
+
+    /// Returns false if there is still no permit after the duration
+    pub fn acquire_timeout(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.try_acquire() {
+            if !futex::wait(&self.permits, 0, Some(deadline)) {
+                return self.try_acquire();
+            }
+        }
+        true
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |p| {
+                if p > 0 {
+                    Some(p - 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+        futex::wake_one(&self.permits);
+    }
+
+    pub fn available(&self) -> u32 {
+        self.permits.load(Ordering::Relaxed)
+    }
+}
@@ -1,11 +1,36 @@
-use erhino_shared::{mem::Address, proc::{Tid, Pid}};
+use core::cell::UnsafeCell;
 
-use crate::call::sys_thread_spawn;
+use alloc::{boxed::Box, sync::Arc};
+use erhino_shared::{
+    mem::Address,
+    proc::{ExitCode, Tid},
+};
 
+use crate::{
+    call::{
+        sys_sleep, sys_thread_exit, sys_thread_join, sys_thread_kill, sys_thread_spawn,
+        sys_thread_yield,
+    },
+    time::{Duration, Instant},
+};
+
+mod local;
+
+pub use self::local::LocalKey;
+
+#[derive(Debug)]
 pub enum ThreadSpawnError {
     KernelError,
 }
 
+#[derive(Debug)]
+pub enum ThreadJoinError {
+    // Thread is not found or has already been joined
+    NotFound,
+    // Thread has been killed or exited before producing its result
+    Exited(ExitCode),
+}
+
 pub struct Thread {
     handle: Tid,
 }
@@ -15,20 +40,102 @@ impl Thread {
         Self { handle }
     }
 
-    pub fn id(&self) -> Tid{
+    pub fn id(&self) -> Tid {
         self.handle
     }
+
+    /// Kill the thread, it can be joined later with its result lost
+    pub fn kill(&self) -> bool {
+        unsafe { sys_thread_kill(self.handle).is_ok() }
+    }
+}
+
+// 由子线程写入，join 返回后（内核保证子线程已经结束）由父线程取出
+struct Packet<T> {
+    result: UnsafeCell<Option<T>>,
+}
+
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+pub struct JoinHandle<T> {
+    thread: Thread,
+    packet: Arc<Packet<T>>,
 }
 
-fn thread_wrapper(tid: Tid, pid: Pid, parent: Pid){
+impl<T> JoinHandle<T> {
+    pub fn thread(&self) -> &Thread {
+        &self.thread
+    }
 
+    /// Block until the thread exits and take its result
+    pub fn join(self) -> Result<T, ThreadJoinError> {
+        let mut code: ExitCode = 0;
+        match unsafe { sys_thread_join(self.thread.handle, &mut code) } {
+            Ok(()) => match unsafe { (*self.packet.result.get()).take() } {
+                Some(result) => Ok(result),
+                None => Err(ThreadJoinError::Exited(code)),
+            },
+            Err(_) => Err(ThreadJoinError::NotFound),
+        }
+    }
 }
 
-pub fn spawn(func: fn()) -> Result<Thread, ThreadSpawnError> {
+extern "C" fn thread_wrapper(main: usize, _tid: Tid) -> ! {
+    let main = unsafe { Box::from_raw(main as *mut Box<dyn FnOnce()>) };
+    main();
+    exit(0)
+}
+
+/// Spawn a thread running the closure in the current process
+pub fn spawn<F, T>(func: F) -> Result<JoinHandle<T>, ThreadSpawnError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: UnsafeCell::new(None),
+    });
+    let their_packet = packet.clone();
+    let main: Box<dyn FnOnce()> = Box::new(move || unsafe {
+        *their_packet.result.get() = Some(func());
+    });
+    let raw = Box::into_raw(Box::new(main));
+    match unsafe { sys_thread_spawn(thread_wrapper as Address, raw as usize) } {
+        Ok(tid) => Ok(JoinHandle {
+            thread: Thread::new(tid),
+            packet,
+        }),
+        Err(_) => {
+            drop(unsafe { Box::from_raw(raw) });
+            Err(ThreadSpawnError::KernelError)
+        }
+    }
+}
+
+/// Terminate the calling thread, the process exits if it's the main thread
+pub fn exit(code: ExitCode) -> ! {
     unsafe {
-        match sys_thread_spawn(thread_wrapper as Address) {
-            Ok(tid) => Ok(Thread::new(tid)),
-            Err(_) => Err(ThreadSpawnError::KernelError),
+        loop {
+            sys_thread_exit(code).expect("this can't be wrong");
         }
     }
 }
+
+/// Block the calling thread for at least the duration
+pub fn sleep(duration: Duration) {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Block the calling thread until the instant
+pub fn sleep_until(deadline: Instant) {
+    unsafe {
+        sys_sleep(deadline.as_nanos()).expect("this can't be wrong");
+    }
+}
+
+/// Give up the rest of the timeslice
+pub fn yield_now() {
+    unsafe {
+        sys_thread_yield().expect("this can't be wrong");
+    }
+}
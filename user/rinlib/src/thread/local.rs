@@ -0,0 +1,64 @@
+use core::cell::Cell;
+
+/// A key to a value owned by each thread, declared with [`thread_local!`](crate::thread_local)
+///
+/// The value is created on the first access of each thread and is never dropped.
+pub struct LocalKey<T: 'static> {
+    slot: unsafe fn() -> *mut Option<T>,
+    init: fn() -> T,
+}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const unsafe fn new(slot: unsafe fn() -> *mut Option<T>, init: fn() -> T) -> Self {
+        Self { slot, init }
+    }
+
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        // 槽位在本线程的 TLS 块里，其他线程摸不到
+        let slot = unsafe { &mut *(self.slot)() };
+        if slot.is_none() {
+            let value = (self.init)();
+            *slot = Some(value);
+        }
+        f(slot.as_ref().unwrap())
+    }
+}
+
+impl<T: Copy + 'static> LocalKey<Cell<T>> {
+    pub fn get(&'static self) -> T {
+        self.with(|cell| cell.get())
+    }
+
+    pub fn set(&'static self, value: T) {
+        self.with(|cell| cell.set(value))
+    }
+}
+
+/// Declare thread local values, same syntax as the one in std
+#[macro_export]
+#[allow_internal_unstable(thread_local)]
+macro_rules! thread_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::thread_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::thread_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::thread::LocalKey<$t> = {
+            unsafe fn __slot() -> *mut ::core::option::Option<$t> {
+                #[thread_local]
+                static mut SLOT: ::core::option::Option<$t> = ::core::option::Option::None;
+                ::core::ptr::addr_of_mut!(SLOT)
+            }
+            fn __init() -> $t {
+                $init
+            }
+            unsafe { $crate::thread::LocalKey::new(__slot, __init) }
+        };
+    };
+}
@@ -0,0 +1,165 @@
+#![no_std]
+
+use core::arch::asm;
+
+use rinlib::{
+    env,
+    preclude::*,
+    process,
+    shared::{
+        call::SystemCall,
+        proc::{ProcessPermission, SystemSignal},
+    },
+    time::{Duration, Instant},
+};
+
+// 以 /boot/bin 启动的是监工，它派生出只有 Valid 权限的工人去乱调用，内核活过所有轮次就算通过
+const SELF_PATH: &str = "/boot/bin/test_fuzz";
+const ROUNDS: usize = 16;
+const CALLS_PER_ROUND: usize = 4096;
+
+// 会让工人合法地结束或永远挂起的调用不在候选里：Exit, ThreadExit, ThreadSpawn(入口随机必然出错), Call(没人回复)
+const CALLS: [SystemCall; 38] = [
+    SystemCall::Die,
+    SystemCall::Debug,
+    SystemCall::Wait,
+    SystemCall::Restrict,
+    SystemCall::ExecuteBytes,
+    SystemCall::ExecuteFile,
+    SystemCall::ThreadYield,
+    SystemCall::ThreadJoin,
+    SystemCall::ThreadKill,
+    SystemCall::SignalReturn,
+    SystemCall::SignalSend,
+    SystemCall::SignalSet,
+    SystemCall::SignalMask,
+    SystemCall::SignalAlarm,
+    SystemCall::Send,
+    SystemCall::Peek,
+    SystemCall::Discard,
+    SystemCall::Receive,
+    SystemCall::Reserve,
+    SystemCall::Reply,
+    SystemCall::Extend,
+    SystemCall::Map,
+    SystemCall::Free,
+    SystemCall::MapPhysical,
+    SystemCall::TunnelBuild,
+    SystemCall::TunnelLink,
+    SystemCall::TunnelDispose,
+    SystemCall::TunnelGrant,
+    SystemCall::TunnelRequest,
+    SystemCall::TunnelResponse,
+    SystemCall::Access,
+    SystemCall::Inspect,
+    SystemCall::Create,
+    SystemCall::Open,
+    SystemCall::Read,
+    SystemCall::Write,
+    SystemCall::Clock,
+    SystemCall::Sleep,
+];
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+}
+
+fn main() {
+    if env::pid() == env::parent_pid() {
+        supervise();
+    } else {
+        work();
+    }
+}
+
+fn supervise() {
+    debug!("fuzz: {} rounds of {} calls", ROUNDS, CALLS_PER_ROUND);
+    for round in 0..ROUNDS {
+        match process::spawn_with_permissions(SELF_PATH, ProcessPermission::Valid) {
+            Ok(worker) => {
+                let pid = worker.id();
+                let code = worker.wait();
+                debug!("fuzz: round {} worker {} exited with {:?}", round, pid, code);
+            }
+            Err(err) => {
+                debug!("fuzz: round {} failed to spawn worker: {:?}", round, err);
+                return;
+            }
+        }
+    }
+    debug!("fuzz: kernel survived {} rounds", ROUNDS);
+}
+
+fn work() {
+    let pid = env::pid();
+    let mut rng = XorShift(Instant::now().as_nanos() as u64 ^ ((pid as u64) << 32) | 1);
+    let local = [0u8; 64];
+    for _ in 0..CALLS_PER_ROUND {
+        let call = if rng.next() % 4 == 0 {
+            // 不认识的调用号
+            rng.next() % 0x100
+        } else {
+            CALLS[rng.next() % CALLS.len()] as usize
+        };
+        let mut args = [0usize; 6];
+        for arg in args.iter_mut() {
+            *arg = argument(&mut rng, local.as_ptr() as usize);
+        }
+        // 只改那些会挂起工人或者伤及监工的参数
+        match call {
+            c if c == SystemCall::SignalSend as usize => {
+                args[0] = pid as usize;
+                if args[1] == SystemSignal::Kill as usize {
+                    args[1] = SystemSignal::Interrupt as usize;
+                }
+            }
+            c if c == SystemCall::Send as usize => args[4] = 0,
+            c if c == SystemCall::Peek as usize => args[2] = 0,
+            c if c == SystemCall::TunnelRequest as usize
+                || c == SystemCall::TunnelResponse as usize =>
+            {
+                args[1] = 0
+            }
+            c if c == SystemCall::Sleep as usize => {
+                let deadline = Instant::now() + Duration::from_micros(args[0] as u64 % 1000);
+                args[0] = deadline.as_nanos() as usize
+            }
+            c if c == SystemCall::Exit as usize
+                || c == SystemCall::ThreadExit as usize
+                || c == SystemCall::ThreadSpawn as usize
+                || c == SystemCall::Call as usize =>
+            {
+                continue
+            }
+            _ => {}
+        }
+        unsafe { raw_call(call, args) };
+    }
+}
+
+fn argument(rng: &mut XorShift, stack: usize) -> usize {
+    match rng.next() % 8 {
+        0 => 0,
+        1 => usize::MAX,
+        2 => rng.next() % 0x1000,
+        3 => rng.next() & !0xFFF,
+        // 用户栈附近
+        4 => stack.wrapping_add(rng.next() % 0x2000).wrapping_sub(0x1000),
+        // 内核空间
+        5 => !0xFFF - (rng.next() % 0x10_0000),
+        6 => 1 << (rng.next() % 64),
+        _ => rng.next(),
+    }
+}
+
+// 结果无所谓，只要内核还活着
+unsafe fn raw_call(id: usize, args: [usize; 6]) {
+    asm!("ecall", in("x17") id, inlateout("x10") args[0] => _, inlateout("x11") args[1] => _, in("x12") args[2], in("x13") args[3], in("x14") args[4], in("x15") args[5]);
+}